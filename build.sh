@@ -24,7 +24,15 @@ esac
 echo "--- Running build and file move script (mode: $BUILD_MODE) ---"
 
 echo "Starting project build"
-cargo build --target wasm32-unknown-emscripten --verbose $CARGO_FLAGS
+if [ "$WSYNTH_THREADS" = "1" ]; then
+    # pthreads build: std has to be rebuilt with atomics, which needs nightly
+    echo "Building with pthreads enabled"
+    cargo +nightly build --target wasm32-unknown-emscripten --verbose $CARGO_FLAGS \
+        -Zbuild-std=std,panic_abort \
+        --config "target.wasm32-unknown-emscripten.rustflags=['-C','target-feature=+atomics,+bulk-memory','-C','link-arg=-pthread','-C','link-arg=-sPTHREAD_POOL_SIZE=navigator.hardwareConcurrency']"
+else
+    cargo build --target wasm32-unknown-emscripten --verbose $CARGO_FLAGS
+fi
 
 echo "Build completed successfully."
 
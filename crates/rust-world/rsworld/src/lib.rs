@@ -47,7 +47,15 @@ pub fn code_aperiodicity(aperiodicity: &Vec<Vec<f64>>, f0_length: i32, fs: i32)
     unsafe {
         GetFFTSizeForCheapTrick(fs, &mut cheaptrick_option as *mut _);
     }
-    let fft_size = cheaptrick_option.fft_size;
+    code_aperiodicity_with_fft_size(aperiodicity, f0_length, fs, cheaptrick_option.fft_size)
+}
+
+pub fn code_aperiodicity_with_fft_size(
+    aperiodicity: &Vec<Vec<f64>>,
+    f0_length: i32,
+    fs: i32,
+    fft_size: i32,
+) -> Vec<Vec<f64>> {
     let aperiodicity_ptr = aperiodicity
         .iter()
         .map(|inner| inner.as_ptr())
@@ -84,7 +92,20 @@ pub fn decode_aperiodicity(
     unsafe {
         GetFFTSizeForCheapTrick(fs, &mut cheaptrick_option as *mut _);
     }
-    let fft_size = cheaptrick_option.fft_size;
+    decode_aperiodicity_with_fft_size(
+        coded_aperiodicity,
+        f0_length,
+        fs,
+        cheaptrick_option.fft_size,
+    )
+}
+
+pub fn decode_aperiodicity_with_fft_size(
+    coded_aperiodicity: &Vec<Vec<f64>>,
+    f0_length: i32,
+    fs: i32,
+    fft_size: i32,
+) -> Vec<Vec<f64>> {
     let coded_aperiodicity_ptr = coded_aperiodicity
         .iter()
         .map(|inner| inner.as_ptr())
@@ -178,13 +199,30 @@ pub fn d4c(
     f0: &Vec<f64>,
     option: &D4COption,
 ) -> Vec<Vec<f64>> {
-    let x_length = x.len() as i32;
-    let f0_length = f0.len() as i32;
     let mut cheaptrick_option = CheapTrickOption::new(fs);
     unsafe {
         GetFFTSizeForCheapTrick(fs, &mut cheaptrick_option as *mut _);
     }
-    let fft_size = cheaptrick_option.fft_size;
+    d4c_with_fft_size(
+        x,
+        fs,
+        temporal_positions,
+        f0,
+        cheaptrick_option.fft_size,
+        option,
+    )
+}
+
+pub fn d4c_with_fft_size(
+    x: &Vec<f64>,
+    fs: i32,
+    temporal_positions: &Vec<f64>,
+    f0: &Vec<f64>,
+    fft_size: i32,
+    option: &D4COption,
+) -> Vec<Vec<f64>> {
+    let x_length = x.len() as i32;
+    let f0_length = f0.len() as i32;
     let mut aperiodicity = vec![vec![0.0; (fft_size / 2 + 1) as usize]; f0_length as usize];
     let mut aperiodicity_ptr = aperiodicity
         .iter_mut()
@@ -286,7 +324,7 @@ pub fn synthesis(
         .collect::<Vec<_>>();
     let aperiodicity_ptr = aperiodicity_ptr.as_ptr();
     let fft_size = (spectrogram[0].len() - 1) * 2;
-    let y_length = f0_length * frame_period as i32 * fs / 1000;
+    let y_length = (f0_length as f64 * frame_period * fs as f64 / 1000.0) as i32;
     let mut y = vec![0.0; y_length as usize];
     unsafe {
         Synthesis(
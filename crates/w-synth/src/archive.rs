@@ -0,0 +1,283 @@
+use crate::encoding::detect_encoding;
+use crate::frq::frq_sample_path;
+use crate::voicebank::VoicebankFiles;
+use crate::world::cache::straycat_sample_path;
+use anyhow::{Result, anyhow};
+use encoding_rs::{Encoding, UTF_8};
+use std::io::{Cursor, Read};
+use zip::ZipArchive;
+
+// a voicebank read out of a zip, paths relative to the bank root with '/' separators
+pub struct VoicebankArchive {
+    // folder of the bank inside the archive, "" when it sits at the top
+    pub root: String,
+    pub name_encoding: &'static Encoding,
+    pub oto_files: Vec<(String, Vec<u8>)>,
+    pub prefix_map: Option<Vec<u8>>,
+    pub character_txt: Option<Vec<u8>>,
+    pub character_yaml: Option<Vec<u8>>,
+    pub readme_txt: Option<Vec<u8>>,
+    pub install_txt: Option<Vec<u8>>,
+    pub wavs: Vec<(String, Vec<u8>)>,
+    // straycat-rs feature files, keyed by the wav they belong to
+    pub features: Vec<(String, Vec<u8>)>,
+    // UTAU .frq frequency maps, keyed the same way
+    pub frqs: Vec<(String, Vec<u8>)>,
+}
+
+impl VoicebankArchive {
+    pub fn metadata_files(&self) -> VoicebankFiles<'_> {
+        VoicebankFiles {
+            character_txt: self.character_txt.as_deref(),
+            character_yaml: self.character_yaml.as_deref(),
+            readme_txt: self.readme_txt.as_deref(),
+            install_txt: self.install_txt.as_deref(),
+        }
+    }
+}
+
+// names without the UTF-8 flag are in whatever the packing system used,
+// usually CP932 for Japanese banks
+fn detect_name_encoding(raw_names: &[Vec<u8>]) -> &'static Encoding {
+    let legacy: Vec<u8> = raw_names
+        .iter()
+        .filter(|name| std::str::from_utf8(name).is_err())
+        .flat_map(|name| name.iter().copied().chain(std::iter::once(b'\n')))
+        .collect();
+    if legacy.is_empty() {
+        return UTF_8;
+    }
+    detect_encoding(&legacy).0
+}
+
+fn decode_name(raw: &[u8], encoding: &'static Encoding) -> String {
+    let name = match std::str::from_utf8(raw) {
+        Ok(name) => name.to_string(),
+        Err(_) => encoding.decode_without_bom_handling(raw).0.into_owned(),
+    };
+    name.replace('\\', "/")
+}
+
+fn split_path(path: &str) -> (&str, &str) {
+    match path.rfind('/') {
+        Some(pos) => (&path[..pos], &path[pos + 1..]),
+        None => ("", path),
+    }
+}
+
+fn depth(dir: &str) -> usize {
+    if dir.is_empty() {
+        0
+    } else {
+        dir.split('/').count()
+    }
+}
+
+fn common_dir<'a>(dirs: impl Iterator<Item = &'a str>) -> String {
+    let mut common: Option<Vec<&str>> = None;
+    for dir in dirs {
+        let parts: Vec<&str> = dir.split('/').filter(|p| !p.is_empty()).collect();
+        common = Some(match common {
+            None => parts,
+            Some(prev) => prev
+                .into_iter()
+                .zip(parts)
+                .take_while(|(a, b)| a == b)
+                .map(|(a, _)| a)
+                .collect(),
+        });
+    }
+    common.unwrap_or_default().join("/")
+}
+
+// the bank root is the shallowest folder with character.txt/yaml, otherwise
+// the folder every oto.ini sits under
+fn find_root(paths: &[String]) -> String {
+    let files: Vec<(&str, String)> = paths
+        .iter()
+        .map(|path| {
+            let (dir, name) = split_path(path);
+            (dir, name.to_lowercase())
+        })
+        .collect();
+
+    let character_dir = files
+        .iter()
+        .filter(|(_, name)| name == "character.txt" || name == "character.yaml")
+        .map(|(dir, _)| *dir)
+        .min_by_key(|dir| depth(dir));
+    match character_dir {
+        Some(dir) => dir.to_string(),
+        None => common_dir(
+            files
+                .iter()
+                .filter(|(_, name)| name == "oto.ini")
+                .map(|(dir, _)| *dir),
+        ),
+    }
+}
+
+pub fn read_voicebank_archive(bytes: &[u8]) -> Result<VoicebankArchive> {
+    let mut zip = ZipArchive::new(Cursor::new(bytes))?;
+
+    let mut raw_names = Vec::new();
+    for i in 0..zip.len() {
+        raw_names.push(zip.by_index_raw(i)?.name_raw().to_vec());
+    }
+    let name_encoding = detect_name_encoding(&raw_names);
+    let paths: Vec<String> = raw_names
+        .iter()
+        .map(|raw| decode_name(raw, name_encoding))
+        .collect();
+
+    // macOS resource forks look like real files
+    let usable = |path: &str| !path.ends_with('/') && !path.starts_with("__MACOSX/");
+    let usable_paths: Vec<String> = paths.iter().filter(|p| usable(p)).cloned().collect();
+    let root = find_root(&usable_paths);
+
+    let mut archive = VoicebankArchive {
+        root: root.clone(),
+        name_encoding,
+        oto_files: Vec::new(),
+        prefix_map: None,
+        character_txt: None,
+        character_yaml: None,
+        readme_txt: None,
+        install_txt: None,
+        wavs: Vec::new(),
+        features: Vec::new(),
+        frqs: Vec::new(),
+    };
+
+    for (i, path) in paths.iter().enumerate() {
+        if !usable(path) {
+            continue;
+        }
+        let relative = if root.is_empty() {
+            path.as_str()
+        } else {
+            match path.strip_prefix(&root).and_then(|p| p.strip_prefix('/')) {
+                Some(relative) => relative,
+                None => continue,
+            }
+        };
+        let (dir, name) = split_path(relative);
+        let name = name.to_lowercase();
+        let feature_of = straycat_sample_path(relative);
+        let frq_of = frq_sample_path(relative);
+        let wanted = name == "oto.ini"
+            || name.ends_with(".wav")
+            || feature_of.is_some()
+            || frq_of.is_some()
+            || (dir.is_empty()
+                && matches!(
+                    name.as_str(),
+                    "prefix.map"
+                        | "character.txt"
+                        | "character.yaml"
+                        | "readme.txt"
+                        | "install.txt"
+                ));
+        if !wanted {
+            continue;
+        }
+
+        let mut file = zip.by_index(i)?;
+        let mut data = Vec::with_capacity(file.size() as usize);
+        file.read_to_end(&mut data)?;
+
+        if let Some(sample_path) = feature_of {
+            archive.features.push((sample_path, data));
+            continue;
+        }
+        if let Some(sample_path) = frq_of {
+            archive.frqs.push((sample_path, data));
+            continue;
+        }
+        match name.as_str() {
+            "oto.ini" => archive.oto_files.push((dir.to_string(), data)),
+            "prefix.map" => archive.prefix_map = Some(data),
+            "character.txt" => archive.character_txt = Some(data),
+            "character.yaml" => archive.character_yaml = Some(data),
+            "readme.txt" => archive.readme_txt = Some(data),
+            "install.txt" => archive.install_txt = Some(data),
+            _ => archive.wavs.push((relative.to_string(), data)),
+        }
+    }
+
+    if archive.oto_files.is_empty() {
+        return Err(anyhow!("No oto.ini found in the archive."));
+    }
+    Ok(archive)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read_voicebank_archive;
+    use encoding_rs::SHIFT_JIS;
+    use flate2::Crc;
+
+    // stored entries with raw CP932 names and no UTF-8 flag, like pre-UTF-8 zip tools write
+    fn legacy_zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut central = Vec::new();
+        for (name, data) in files {
+            let (name, _, _) = SHIFT_JIS.encode(name);
+            let mut crc = Crc::new();
+            crc.update(data);
+            let offset = bytes.len() as u32;
+
+            let mut fields = Vec::new();
+            fields.extend_from_slice(&[20, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+            fields.extend_from_slice(&crc.sum().to_le_bytes());
+            fields.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            fields.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            fields.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            fields.extend_from_slice(&[0, 0]);
+
+            bytes.extend_from_slice(&0x04034b50u32.to_le_bytes());
+            bytes.extend_from_slice(&fields);
+            bytes.extend_from_slice(&name);
+            bytes.extend_from_slice(data);
+
+            central.extend_from_slice(&0x02014b50u32.to_le_bytes());
+            central.extend_from_slice(&[20, 0]);
+            central.extend_from_slice(&fields);
+            central.extend_from_slice(&[0; 10]);
+            central.extend_from_slice(&offset.to_le_bytes());
+            central.extend_from_slice(&name);
+        }
+        let central_offset = bytes.len() as u32;
+        bytes.extend_from_slice(&central);
+        bytes.extend_from_slice(&0x06054b50u32.to_le_bytes());
+        bytes.extend_from_slice(&[0; 4]);
+        bytes.extend_from_slice(&(files.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(&(files.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(&(central.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&central_offset.to_le_bytes());
+        bytes.extend_from_slice(&[0, 0]);
+        bytes
+    }
+
+    #[test]
+    fn test_read_cp932_archive() {
+        let bytes = legacy_zip(&[
+            ("テト/character.txt", b"name=teto\r\n"),
+            ("テト/oto.ini", b"a.wav=a,0,100,-200,50,20\r\n"),
+            ("テト/強/あ.wav", b"RIFF"),
+            ("テト/強/oto.ini", b""),
+            ("__MACOSX/テト/._oto.ini", b""),
+        ]);
+
+        let archive = read_voicebank_archive(&bytes).unwrap();
+        assert_eq!(archive.name_encoding, SHIFT_JIS);
+        assert_eq!(archive.root, "テト");
+        assert_eq!(archive.oto_files.len(), 2);
+        assert_eq!(archive.oto_files[1].0, "強");
+        assert_eq!(archive.wavs[0].0, "強/あ.wav");
+        assert_eq!(
+            archive.character_txt.as_deref(),
+            Some(&b"name=teto\r\n"[..])
+        );
+    }
+}
@@ -0,0 +1,265 @@
+use crate::oto::{OtoEntry, alias_from_filename};
+use crate::world::features::WorldFeatures;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SampleStyle {
+    Cv,
+    Vcv,
+}
+
+impl SampleStyle {
+    // mapping used by the FFI
+    pub fn from_code(code: i32) -> Option<Self> {
+        match code {
+            0 => Some(SampleStyle::Cv),
+            1 => Some(SampleStyle::Vcv),
+            _ => None,
+        }
+    }
+}
+
+// margins in ms, picked to match what hand-made banks usually look like
+const OFFSET_MARGIN_MS: f64 = 20.;
+const CUTOFF_MARGIN_MS: f64 = 30.;
+const MIN_VOICED_MS: f64 = 30.;
+const TRANSITION_SEARCH_MS: f64 = 120.;
+const MIN_FIXED_MS: f64 = 30.;
+const MAX_FIXED_MS: f64 = 200.;
+const VCV_LEAD_MS: f64 = 100.;
+
+fn moving_average(values: &[f64], radius: usize) -> Vec<f64> {
+    (0..values.len())
+        .map(|i| {
+            let window = &values[i.saturating_sub(radius)..(i + radius + 1).min(values.len())];
+            window.iter().sum::<f64>() / window.len() as f64
+        })
+        .collect()
+}
+
+fn percentile(values: &[f64], p: f64) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    sorted[((sorted.len() - 1) as f64 * p).round() as usize]
+}
+
+// c0 of the coded envelope follows the frame log energy
+fn energy_envelope(features: &WorldFeatures) -> Vec<f64> {
+    features
+        .mgc
+        .iter()
+        .map(|frame| frame.first().copied().unwrap_or(0.))
+        .collect()
+}
+
+fn spectral_flux(features: &WorldFeatures) -> Vec<f64> {
+    let mut flux = vec![0.];
+    flux.extend(features.mgc.windows(2).map(|pair| {
+        pair[1]
+            .iter()
+            .zip(pair[0].iter())
+            .skip(1)
+            .map(|(a, b)| (a - b) * (a - b))
+            .sum::<f64>()
+            .sqrt()
+    }));
+    flux
+}
+
+// [start, end) frame ranges where f0 stays voiced for at least min_len frames
+fn voiced_runs(f0: &[f64], min_len: usize) -> Vec<(usize, usize)> {
+    let mut runs = Vec::new();
+    let mut start = None;
+    for (i, f) in f0.iter().chain(std::iter::once(&0.)).enumerate() {
+        match (start, *f > 0.) {
+            (None, true) => start = Some(i),
+            (Some(s), false) => {
+                if i - s >= min_len {
+                    runs.push((s, i));
+                }
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    runs
+}
+
+fn argmax(values: &[f64], from: usize, to: usize) -> Option<usize> {
+    (from..to.min(values.len())).max_by(|a, b| values[*a].total_cmp(&values[*b]))
+}
+
+struct Analysis {
+    frame_period: f64,
+    energy: Vec<f64>,
+    flux: Vec<f64>,
+    runs: Vec<(usize, usize)>,
+    sound_start: usize,
+    sound_end: usize,
+}
+
+impl Analysis {
+    fn new(features: &WorldFeatures) -> Option<Self> {
+        let frame_period = features.config.frame_period;
+        if features.f0.len() < 4 || features.mgc.len() != features.f0.len() {
+            return None;
+        }
+        let energy = moving_average(&energy_envelope(features), 2);
+        let flux = moving_average(&spectral_flux(features), 1);
+
+        let floor = percentile(&energy, 0.1);
+        let peak = percentile(&energy, 0.95);
+        if peak <= floor {
+            return None;
+        }
+        let threshold = floor + 0.3 * (peak - floor);
+        let sound_start = energy.iter().position(|e| *e > threshold)?;
+        let sound_end = energy.iter().rposition(|e| *e > threshold)? + 1;
+
+        let min_voiced = (MIN_VOICED_MS / frame_period).ceil() as usize;
+        let runs = voiced_runs(&features.f0, min_voiced.max(1))
+            .into_iter()
+            .filter(|(s, e)| *e > sound_start && *s < sound_end)
+            .collect();
+
+        Some(Self {
+            frame_period,
+            energy,
+            flux,
+            runs,
+            sound_start,
+            sound_end,
+        })
+    }
+
+    fn ms(&self, frame: usize) -> f64 {
+        frame as f64 * self.frame_period
+    }
+
+    fn frames(&self, ms: f64) -> usize {
+        (ms / self.frame_period).round() as usize
+    }
+
+    // where a voiced run turns into the vowel: the voicing onset for unvoiced
+    // consonants, the strongest spectral change for voiced ones
+    fn vowel_onset(&self, run: (usize, usize), consonant_start: usize) -> usize {
+        let (start, end) = run;
+        if start > consonant_start + self.frames(OFFSET_MARGIN_MS) {
+            return start;
+        }
+        let search_end = (start + self.frames(TRANSITION_SEARCH_MS)).min(end);
+        let median = percentile(&self.flux[start..end], 0.5);
+        match argmax(&self.flux, start + 2, search_end) {
+            Some(peak) if self.flux[peak] > 2. * median => peak,
+            _ => start,
+        }
+    }
+
+    // first frame after the onset where the spectrum settles down
+    fn stable_point(&self, onset: usize, run_end: usize) -> usize {
+        let last = self.flux.len() - 1;
+        let onset = onset.min(last);
+        let min = (onset + self.frames(MIN_FIXED_MS)).min(last);
+        let max = (onset + self.frames(MAX_FIXED_MS))
+            .min(run_end.max(min))
+            .min(last);
+        let median = percentile(&self.flux[onset..=run_end.clamp(onset, last)], 0.5);
+        (min..max).find(|i| self.flux[*i] <= median).unwrap_or(max)
+    }
+}
+
+pub fn estimate_oto_entry(
+    filename: &str,
+    features: &WorldFeatures,
+    style: SampleStyle,
+) -> Option<OtoEntry> {
+    let analysis = Analysis::new(features)?;
+    let sound_start = analysis.sound_start;
+    let sound_end = analysis.sound_end;
+
+    let (offset_ms, vowel_onset, run_end, overlap_ms) = match style {
+        SampleStyle::Cv => {
+            let run = analysis.runs.first().copied();
+            let onset = run.map_or(sound_start, |run| analysis.vowel_onset(run, sound_start));
+            let offset_ms = (analysis.ms(sound_start) - OFFSET_MARGIN_MS).max(0.);
+            let preutterance_ms = analysis.ms(onset) - offset_ms;
+            (
+                offset_ms,
+                onset,
+                run.map_or(sound_end, |run| run.1),
+                preutterance_ms / 3.,
+            )
+        }
+        SampleStyle::Vcv => {
+            let first = *analysis.runs.first()?;
+            let (consonant_start, run) = match analysis.runs.get(1) {
+                Some(second) => (first.1, *second),
+                None => {
+                    // voiced consonant, the boundary is the energy dip inside the run
+                    let from = first.0 + analysis.frames(VCV_LEAD_MS).min((first.1 - first.0) / 2);
+                    let dip = (from..first.1)
+                        .min_by(|a, b| analysis.energy[*a].total_cmp(&analysis.energy[*b]))?;
+                    (dip, (dip, first.1))
+                }
+            };
+            let onset = analysis.vowel_onset(run, consonant_start);
+            let offset_ms = (analysis.ms(consonant_start) - VCV_LEAD_MS).max(analysis.ms(first.0));
+            let overlap_ms = (analysis.ms(consonant_start) - offset_ms) / 2.;
+            (offset_ms, onset, run.1, overlap_ms)
+        }
+    };
+
+    let preutterance_ms = analysis.ms(vowel_onset) - offset_ms;
+    let stable = analysis.stable_point(vowel_onset, run_end);
+    let consonant_ms = (analysis.ms(stable) - offset_ms).max(preutterance_ms + MIN_FIXED_MS);
+    let end_ms = (analysis.ms(sound_end) - CUTOFF_MARGIN_MS)
+        .max(offset_ms + consonant_ms + analysis.frame_period);
+
+    Some(OtoEntry {
+        filename: filename.to_string(),
+        alias: alias_from_filename(filename),
+        offset: offset_ms,
+        consonant: consonant_ms,
+        // negative cutoff, measured from the offset
+        cutoff: -(end_ms - offset_ms),
+        preutterance: preutterance_ms,
+        overlap: overlap_ms.max(0.),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SampleStyle, estimate_oto_entry};
+    use crate::world::features::{AnalysisConfig, WorldFeatures};
+
+    #[test]
+    fn test_estimate_cv() {
+        // 100 ms silence, 60 ms unvoiced consonant, 400 ms vowel, 100 ms silence
+        let frames = 132;
+        let f0: Vec<f64> = (0..frames)
+            .map(|i| if (32..112).contains(&i) { 220. } else { 0. })
+            .collect();
+        let mgc: Vec<Vec<f64>> = (0..frames)
+            .map(|i| match i {
+                20..32 => vec![-2., 1.5, -0.5],
+                32..112 => vec![0., 0.2, 0.1],
+                _ => vec![-10., 0., 0.],
+            })
+            .collect();
+        let features = WorldFeatures {
+            config: AnalysisConfig::default(),
+            base_f0: 220.,
+            f0,
+            bap: vec![vec![0.; 2]; frames],
+            mgc,
+        };
+
+        let entry = estimate_oto_entry("sub/ka.wav", &features, SampleStyle::Cv).unwrap();
+        assert_eq!(entry.alias, "ka");
+        assert!((entry.offset - 80.).abs() <= 10.);
+        assert!((entry.offset + entry.preutterance - 160.).abs() <= 10.);
+        assert!(entry.consonant > entry.preutterance);
+        assert!(entry.overlap < entry.preutterance);
+        assert!(entry.cutoff < 0.);
+        assert!(entry.offset - entry.cutoff <= 560.);
+    }
+}
@@ -0,0 +1,21 @@
+use crate::audio::read_write::read_audio;
+use crate::frq::FrqData;
+use crate::parallel;
+use crate::world::features::{AnalysisConfig, WorldFeatures, generate_features};
+use anyhow::Result;
+use std::collections::HashMap;
+
+pub type AnalysisResult = (String, Result<WorldFeatures>);
+
+pub fn analyze_batch(
+    files: &[(String, Vec<u8>)],
+    config: &AnalysisConfig,
+    frqs: &HashMap<String, FrqData>,
+    threads: usize,
+) -> Vec<AnalysisResult> {
+    parallel::map_ordered(files, threads, |(filename, data)| {
+        let frq = frqs.get(filename);
+        let features = read_audio(data).and_then(|audio| generate_features(audio, config, frq));
+        (filename.clone(), features)
+    })
+}
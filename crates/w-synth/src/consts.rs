@@ -1,6 +1,6 @@
 // Resamplers only work in 44100 16-bit WAV
 pub const SAMPLE_RATE: u32 = 44100;
-// WORLD constants
+// WORLD constants, defaults for AnalysisConfig
 pub const SPEC_Q1: f64 = -0.15;
 pub const F0_FLOOR: f64 = 71.;
 pub const F0_CEIL: f64 = 1760.;
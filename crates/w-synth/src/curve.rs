@@ -0,0 +1,65 @@
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct CurvePoint {
+    // ms from the start of the project
+    pub time: f64,
+    pub value: f64,
+}
+
+// a project-wide curve over absolute time, linear between points and held flat past
+// the first and last one; points may arrive in any order
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(from = "Vec<CurvePoint>")]
+pub struct Curve {
+    points: Vec<CurvePoint>,
+}
+
+impl From<Vec<CurvePoint>> for Curve {
+    fn from(mut points: Vec<CurvePoint>) -> Self {
+        points.retain(|p| p.time.is_finite() && p.value.is_finite());
+        points.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Self { points }
+    }
+}
+
+impl Curve {
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    // 0 when the curve has no points
+    pub fn sample(&self, time_ms: f64) -> f64 {
+        let next = self.points.partition_point(|p| p.time <= time_ms);
+        match (
+            next.checked_sub(1).map(|i| self.points[i]),
+            self.points.get(next),
+        ) {
+            (None, None) => 0.,
+            (Some(p), None) | (None, Some(&p)) => p.value,
+            (Some(p1), Some(p2)) => {
+                let ratio = (time_ms - p1.time) / (p2.time - p1.time);
+                p1.value + (p2.value - p1.value) * ratio
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Curve;
+
+    #[test]
+    fn test_sample_curve() {
+        let curve: Curve = serde_json::from_str(
+            r#"[{"time": 200, "value": 0}, {"time": 100, "value": 50}, {"time": 300, "value": -50}]"#,
+        )
+        .unwrap();
+        assert_eq!(curve.sample(0.), 50.);
+        assert_eq!(curve.sample(100.), 50.);
+        assert_eq!(curve.sample(150.), 25.);
+        assert_eq!(curve.sample(250.), -25.);
+        assert_eq!(curve.sample(1000.), -50.);
+        assert_eq!(Curve::default().sample(100.), 0.);
+    }
+}
@@ -0,0 +1,199 @@
+use encoding_rs::{Encoding, GBK, SHIFT_JIS, UTF_8, UTF_16BE, UTF_16LE};
+
+// legacy encodings tried when the bytes aren't UTF-8, first one wins a tie
+const LEGACY_ENCODINGS: [&Encoding; 3] = [SHIFT_JIS, GBK, UTF_8];
+
+// oto.ini is mostly ASCII, so UTF-16 LE without a BOM shows up as zero high bytes
+fn looks_like_utf16le(bytes: &[u8]) -> bool {
+    if bytes.len() < 4 || !bytes.len().is_multiple_of(2) {
+        return false;
+    }
+    let units = bytes.len() / 2;
+    let zero_high = bytes.chunks_exact(2).filter(|unit| unit[1] == 0).count();
+    let zero_low = bytes.chunks_exact(2).filter(|unit| unit[0] == 0).count();
+    zero_high * 10 >= units * 3 && zero_low * 10 < units
+}
+
+// characters a wrong guess tends to produce: halfwidth kana when GBK is read as
+// Shift-JIS, private use when Shift-JIS is read as GBK
+fn is_suspicious(c: char) -> bool {
+    matches!(c, '\u{FF61}'..='\u{FF9F}' | '\u{E000}'..='\u{F8FF}')
+}
+
+fn score(bytes: &[u8], encoding: &'static Encoding) -> usize {
+    let (text, _) = encoding.decode_without_bom_handling(bytes);
+    text.chars()
+        .map(|c| match c {
+            '\u{FFFD}' => 4,
+            c if is_suspicious(c) => 1,
+            _ => 0,
+        })
+        .sum()
+}
+
+// returns the encoding and the length of its BOM
+pub fn detect_encoding(bytes: &[u8]) -> (&'static Encoding, usize) {
+    if let Some((encoding, bom_len)) = Encoding::for_bom(bytes) {
+        return (encoding, bom_len);
+    }
+    if looks_like_utf16le(bytes) {
+        return (UTF_16LE, 0);
+    }
+    if std::str::from_utf8(bytes).is_ok() {
+        return (UTF_8, 0);
+    }
+    let encoding = LEGACY_ENCODINGS
+        .into_iter()
+        .min_by_key(|encoding| score(bytes, encoding))
+        .unwrap_or(SHIFT_JIS);
+    (encoding, 0)
+}
+
+// UTAU files are Shift-JIS unless they say otherwise; a BOM wins, then valid UTF-8
+pub fn decode_text(bytes: &[u8]) -> (String, &'static Encoding) {
+    let (encoding, bom_len) = detect_encoding(bytes);
+    let (text, _) = encoding.decode_without_bom_handling(&bytes[bom_len..]);
+    (text.into_owned(), encoding)
+}
+
+#[derive(Debug, Clone)]
+pub struct DecodedText {
+    pub encoding: &'static Encoding,
+    pub bom: bool,
+    pub lines: Vec<String>,
+    // 1-based numbers of lines with bytes the encoding can't read
+    pub bad_lines: Vec<usize>,
+    pub line_ending: &'static str,
+}
+
+fn split_lines<'a>(bytes: &'a [u8], encoding: &'static Encoding) -> Vec<&'a [u8]> {
+    let newline: &[u8] = if encoding == UTF_16LE {
+        &[b'\n', 0]
+    } else if encoding == UTF_16BE {
+        &[0, b'\n']
+    } else {
+        b"\n"
+    };
+    let mut lines = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+    while i + newline.len() <= bytes.len() {
+        if &bytes[i..i + newline.len()] == newline {
+            lines.push(&bytes[start..i]);
+            start = i + newline.len();
+        }
+        i += newline.len();
+    }
+    if start < bytes.len() {
+        lines.push(&bytes[start..]);
+    }
+    lines
+}
+
+// decodes line by line so one broken line doesn't take the rest of the file with it
+pub fn decode_lines(bytes: &[u8]) -> DecodedText {
+    let (encoding, bom_len) = detect_encoding(bytes);
+    let mut lines = Vec::new();
+    let mut bad_lines = Vec::new();
+    let mut crlf = false;
+
+    for (index, raw) in split_lines(&bytes[bom_len..], encoding)
+        .into_iter()
+        .enumerate()
+    {
+        let (text, had_errors) = encoding.decode_without_bom_handling(raw);
+        if had_errors {
+            bad_lines.push(index + 1);
+        }
+        let text = match text.strip_suffix('\r') {
+            Some(stripped) => {
+                crlf = true;
+                stripped.to_string()
+            }
+            None => text.into_owned(),
+        };
+        lines.push(text);
+    }
+
+    DecodedText {
+        encoding,
+        bom: bom_len > 0,
+        lines,
+        bad_lines,
+        line_ending: if crlf { "\r\n" } else { "\n" },
+    }
+}
+
+// writes text back in the encoding it was read with; text the encoding can't
+// hold falls back to UTF-8 with a BOM so it is detected on the next load
+pub fn encode_text(text: &str, encoding: &'static Encoding, bom: bool) -> Vec<u8> {
+    if encoding == UTF_16LE || encoding == UTF_16BE {
+        let mut bytes = Vec::with_capacity(text.len() * 2 + 2);
+        for unit in std::iter::once(0xFEFF).chain(text.encode_utf16()) {
+            if encoding == UTF_16LE {
+                bytes.extend_from_slice(&unit.to_le_bytes());
+            } else {
+                bytes.extend_from_slice(&unit.to_be_bytes());
+            }
+        }
+        return bytes;
+    }
+    let (bytes, _, had_errors) = encoding.encode(text);
+    // UTF-8 keeps its BOM if it had one
+    let needs_bom = had_errors || (bom && encoding == UTF_8);
+    if !needs_bom {
+        return bytes.into_owned();
+    }
+    let mut bytes = vec![0xEF, 0xBB, 0xBF];
+    bytes.extend_from_slice(text.as_bytes());
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_lines, encode_text};
+    use encoding_rs::{GBK, SHIFT_JIS, UTF_8, UTF_16LE};
+
+    #[test]
+    fn test_detect_encodings() {
+        let text = "あ.wav=- あ,0,100,-200,50,20\r\nか.wav=a か,0,100,-200,50,20\r\n";
+
+        let (sjis, _, _) = SHIFT_JIS.encode(text);
+        let decoded = decode_lines(&sjis);
+        assert_eq!(decoded.encoding, SHIFT_JIS);
+        assert_eq!(decoded.lines[1], "か.wav=a か,0,100,-200,50,20");
+        assert_eq!(decoded.line_ending, "\r\n");
+        assert_eq!(encode_text("あ", decoded.encoding, decoded.bom), &sjis[..2]);
+
+        let gbk_text = "啊.wav=啊,0,100,-200,50,20\n哦.wav=哦,0,100,-200,50,20\n";
+        let (gbk, _, _) = GBK.encode(gbk_text);
+        let decoded = decode_lines(&gbk);
+        assert_eq!(decoded.encoding, GBK);
+        assert_eq!(decoded.lines[0], "啊.wav=啊,0,100,-200,50,20");
+
+        let utf16: Vec<u8> = text.encode_utf16().flat_map(|u| u.to_le_bytes()).collect();
+        let decoded = decode_lines(&utf16);
+        assert_eq!(decoded.encoding, UTF_16LE);
+        assert_eq!(decoded.lines.len(), 2);
+        assert_eq!(decoded.lines[0], "あ.wav=- あ,0,100,-200,50,20");
+
+        let with_bom = [&[0xEF, 0xBB, 0xBF][..], text.as_bytes()].concat();
+        let decoded = decode_lines(&with_bom);
+        assert_eq!(decoded.encoding, UTF_8);
+        assert!(decoded.bom);
+        assert_eq!(decoded.lines[0], "あ.wav=- あ,0,100,-200,50,20");
+    }
+
+    #[test]
+    fn test_bad_line_is_reported() {
+        let mut bytes = "a.wav=a,0,100,-200,50,20\n".as_bytes().to_vec();
+        bytes.extend_from_slice(b"b.wav=b\xff\xfe,0,100,-200,50,20\n");
+        bytes.extend_from_slice("い.wav=い,0,100,-200,50,20\n".as_bytes());
+
+        let decoded = decode_lines(&bytes);
+        assert_eq!(decoded.encoding, UTF_8);
+        assert_eq!(decoded.bad_lines, vec![2]);
+        assert_eq!(decoded.lines.len(), 3);
+        assert_eq!(decoded.lines[2], "い.wav=い,0,100,-200,50,20");
+    }
+}
@@ -0,0 +1,127 @@
+use crate::consts;
+use crate::curve::Curve;
+use crate::util::{self, smoothstep};
+use serde::Deserialize;
+
+// project-wide automation over absolute time, all 0 where a curve has no points
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Expressions {
+    // dB of gain
+    pub dynamics: Curve,
+    // added to the note's B flag, -100..100
+    pub breathiness: Curve,
+    // formant shift in cents, positive is deeper like the g flag
+    pub gender: Curve,
+    // spectral tilt around 1 kHz, -100..100
+    pub tension: Curve,
+    // high shelf above ~3 kHz, -100..100
+    pub brightness: Curve,
+}
+
+// dB of tilt per octave away from the pivot at full tension
+const TENSION_DB_PER_OCTAVE: f64 = 3.;
+const TENSION_PIVOT_HZ: f64 = 1000.;
+const BRIGHTNESS_MAX_DB: f64 = 12.;
+const BRIGHTNESS_LOW_HZ: f64 = 1500.;
+const BRIGHTNESS_HIGH_HZ: f64 = 4000.;
+
+impl Expressions {
+    pub fn has_spectral(&self) -> bool {
+        !(self.gender.is_empty() && self.tension.is_empty() && self.brightness.is_empty())
+    }
+
+    // harmonic level for the note's B flag plus the breathiness curve, kept in the
+    // flag's 0..100 range so the harmonics never go past what B0 or B100 give
+    pub fn harmonic_mix(&self, flag_breathiness: f64, time_ms: f64) -> f64 {
+        let breathiness = (flag_breathiness + self.breathiness.sample(time_ms)).clamp(0., 100.);
+        1. - 2. * (breathiness / 100. - 0.5)
+    }
+
+    // linear gain of the dynamics curve
+    pub fn gain(&self, time_ms: f64) -> f64 {
+        10f64.powf(self.dynamics.sample(time_ms) / 20.)
+    }
+
+    // reshapes one frame of the (power) spectral envelope at time_ms
+    pub fn apply_spectral(&self, frame: &mut [f64], fft_size: i32, time_ms: f64) {
+        let gender = self.gender.sample(time_ms);
+        let tension = self.tension.sample(time_ms).clamp(-100., 100.) / 100.;
+        let brightness = self.brightness.sample(time_ms).clamp(-100., 100.) / 100.;
+        let bin_hz = consts::SAMPLE_RATE as f64 / fft_size as f64;
+
+        if gender != 0. {
+            warp_formants(frame, 2f64.powf(gender / 1200.));
+        }
+        if tension == 0. && brightness == 0. {
+            return;
+        }
+        for (k, value) in frame.iter_mut().enumerate().skip(1) {
+            let hz = k as f64 * bin_hz;
+            let tilt_db = tension * TENSION_DB_PER_OCTAVE * (hz / TENSION_PIVOT_HZ).log2();
+            let shelf_db = brightness
+                * BRIGHTNESS_MAX_DB
+                * smoothstep(BRIGHTNESS_LOW_HZ, BRIGHTNESS_HIGH_HZ, hz);
+            *value *= 10f64.powf((tilt_db + shelf_db) / 10.);
+        }
+    }
+}
+
+// reads each bin from `ratio` times its frequency, so ratio > 1 pulls formants down
+fn warp_formants(frame: &mut [f64], ratio: f64) {
+    let source = frame.to_vec();
+    let last = source.len() - 1;
+    for (k, value) in frame.iter_mut().enumerate() {
+        let position = (k as f64 * ratio).min(last as f64);
+        let index = position.floor() as usize;
+        let next = (index + 1).min(last);
+        *value = util::lerp(source[index], source[next], position - index as f64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Expressions;
+
+    #[test]
+    fn test_breathiness_and_dynamics() {
+        let neutral = Expressions::default();
+        assert_eq!(neutral.harmonic_mix(50., 0.), 1.);
+        assert_eq!(neutral.gain(0.), 1.);
+
+        let expressions: Expressions = serde_json::from_str(
+            r#"{
+                "breathiness": [{"time": 0, "value": 100}, {"time": 1000, "value": -100}],
+                "dynamics": [{"time": 0, "value": -6}, {"time": 1000, "value": 20}]
+            }"#,
+        )
+        .unwrap();
+        // B50 + 100 and B50 - 100 stop at the ends of the flag's range
+        assert_eq!(expressions.harmonic_mix(50., 0.), 0.);
+        assert_eq!(expressions.harmonic_mix(50., 1000.), 2.);
+        assert_eq!(expressions.harmonic_mix(50., 500.), 1.);
+        assert!((expressions.gain(0.) - 0.501).abs() < 1e-3);
+        assert!((expressions.gain(1000.) - 10.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_spectral_expressions() {
+        let neutral = Expressions::default();
+        assert!(!neutral.has_spectral());
+        let mut frame = vec![1.; 1025];
+        neutral.apply_spectral(&mut frame, 2048, 0.);
+        assert!(frame.iter().all(|v| *v == 1.));
+
+        let expressions: Expressions = serde_json::from_str(
+            r#"{"tension": [{"time": 0, "value": 100}], "gender": [{"time": 0, "value": 1200}]}"#,
+        )
+        .unwrap();
+        assert!(expressions.has_spectral());
+        // a peak at bin 100 moves down an octave, then the tilt cuts lows and lifts highs
+        let mut frame = vec![1.; 1025];
+        frame[100] = 4.;
+        expressions.apply_spectral(&mut frame, 2048, 0.);
+        assert!(frame[50] > frame[49] && frame[50] > frame[51]);
+        assert!(frame[10] < 1. && frame[1000] > 1.);
+    }
+}
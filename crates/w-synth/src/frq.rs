@@ -0,0 +1,134 @@
+use anyhow::{Result, anyhow};
+
+const FRQ_MAGIC: &[u8; 8] = b"FREQ0003";
+// magic, samples per frame, average f0, 16 unused bytes, frame count
+const FRQ_HEADER_LEN: usize = 40;
+
+// UTAU frequency map as written by fresamp/resampler.exe
+#[derive(Debug, Clone)]
+pub struct FrqData {
+    pub samples_per_frame: usize,
+    pub f0: Vec<f64>,
+    pub amplitude: Vec<f64>,
+}
+
+fn read_f64(bytes: &[u8], at: usize) -> f64 {
+    f64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+fn read_i32(bytes: &[u8], at: usize) -> i32 {
+    i32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+pub fn parse_frq(bytes: &[u8]) -> Result<FrqData> {
+    if bytes.len() < FRQ_HEADER_LEN || &bytes[..8] != FRQ_MAGIC {
+        return Err(anyhow!("Not a frq file (missing FREQ0003 header)."));
+    }
+    // the average f0 at byte 12 is recomputed from the track by generate_features
+    let samples_per_frame = read_i32(bytes, 8);
+    let frames = read_i32(bytes, 36);
+    if samples_per_frame <= 0 || frames < 0 {
+        return Err(anyhow!(
+            "Invalid frq header: {} samples per frame, {} frames.",
+            samples_per_frame,
+            frames
+        ));
+    }
+
+    let frames = frames as usize;
+    let body = &bytes[FRQ_HEADER_LEN..];
+    // some writers stop early, keep the frames that are there
+    let available = (body.len() / 16).min(frames);
+    let mut f0 = Vec::with_capacity(available);
+    let mut amplitude = Vec::with_capacity(available);
+    for i in 0..available {
+        let frequency = read_f64(body, i * 16);
+        f0.push(if frequency.is_finite() { frequency } else { 0. });
+        amplitude.push(read_f64(body, i * 16 + 8));
+    }
+
+    Ok(FrqData {
+        samples_per_frame: samples_per_frame as usize,
+        f0,
+        amplitude,
+    })
+}
+
+impl FrqData {
+    // f0 at WORLD frame times; silent frames and f0 outside [f0_floor, f0_ceil] count as
+    // unvoiced, voiced neighbours are interpolated linearly, an edge takes the nearer one
+    pub fn resample(
+        &self,
+        sample_rate: u32,
+        frame_period: f64,
+        frames: usize,
+        f0_floor: f64,
+        f0_ceil: f64,
+    ) -> Vec<f64> {
+        let voiced = |i: usize| match (self.f0.get(i), self.amplitude.get(i)) {
+            (Some(&f), Some(&a)) if a > 0. && (f0_floor..=f0_ceil).contains(&f) => f,
+            _ => 0.,
+        };
+        let frq_period_ms = self.samples_per_frame as f64 * 1000. / sample_rate as f64;
+
+        (0..frames)
+            .map(|i| {
+                let position = i as f64 * frame_period / frq_period_ms;
+                let index = position.floor() as usize;
+                let current = voiced(index);
+                let next = voiced(index + 1);
+                let frac = position - index as f64;
+                match (current > 0., next > 0.) {
+                    (true, true) => current + (next - current) * frac,
+                    _ if frac < 0.5 => current,
+                    _ => next,
+                }
+            })
+            .collect()
+    }
+}
+
+// the sample an UTAU frequency map belongs to: "a_wav.frq" -> "a.wav"
+pub fn frq_sample_path(path: &str) -> Option<String> {
+    let stem_len = path.len().checked_sub("_wav.frq".len())?;
+    if !path.is_char_boundary(stem_len) || !path[stem_len..].eq_ignore_ascii_case("_wav.frq") {
+        return None;
+    }
+    Some(format!("{}.wav", &path[..stem_len]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{frq_sample_path, parse_frq};
+
+    #[test]
+    fn test_parse_and_resample() {
+        let mut bytes = b"FREQ0003".to_vec();
+        bytes.extend_from_slice(&256i32.to_le_bytes());
+        bytes.extend_from_slice(&220f64.to_le_bytes());
+        bytes.extend_from_slice(&[0; 16]);
+        bytes.extend_from_slice(&4i32.to_le_bytes());
+        for frequency in [0., 200., 220., 240.] {
+            bytes.extend_from_slice(&f64::to_le_bytes(frequency));
+            bytes.extend_from_slice(&1000f64.to_le_bytes());
+        }
+
+        let frq = parse_frq(&bytes).unwrap();
+        assert_eq!(frq.samples_per_frame, 256);
+        assert_eq!(frq.f0, vec![0., 200., 220., 240.]);
+
+        // 256 samples at 44100 Hz is about 5.8 ms per frq frame
+        let f0 = frq.resample(44100, 5., 6, 71., 1760.);
+        assert_eq!(f0[0], 0.);
+        assert!(f0[2] > 200. && f0[2] < 220.);
+        assert!(f0[3] > 220. && f0[3] < 240.);
+        assert_eq!(f0[5], 0.);
+
+        assert_eq!(
+            frq_sample_path("強/あ_wav.frq").as_deref(),
+            Some("強/あ.wav")
+        );
+        assert_eq!(frq_sample_path("a.frq"), None);
+        assert!(parse_frq(b"FREQ0002").is_err());
+    }
+}
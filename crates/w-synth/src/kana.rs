@@ -0,0 +1,198 @@
+use serde::Serialize;
+
+// consonant, then the kana for a, i, u, e, o ("" where the row has none)
+const KANA_ROWS: &[(&str, [&str; 5])] = &[
+    ("", ["あ", "い", "う", "え", "お"]),
+    ("k", ["か", "き", "く", "け", "こ"]),
+    ("g", ["が", "ぎ", "ぐ", "げ", "ご"]),
+    ("s", ["さ", "", "す", "せ", "そ"]),
+    ("sh", ["しゃ", "し", "しゅ", "しぇ", "しょ"]),
+    ("z", ["ざ", "", "ず", "ぜ", "ぞ"]),
+    ("j", ["じゃ", "じ", "じゅ", "じぇ", "じょ"]),
+    ("t", ["た", "てぃ", "とぅ", "て", "と"]),
+    ("ch", ["ちゃ", "ち", "ちゅ", "ちぇ", "ちょ"]),
+    ("ts", ["つぁ", "つぃ", "つ", "つぇ", "つぉ"]),
+    ("d", ["だ", "でぃ", "どぅ", "で", "ど"]),
+    ("n", ["な", "に", "ぬ", "ね", "の"]),
+    ("ny", ["にゃ", "", "にゅ", "にぇ", "にょ"]),
+    ("h", ["は", "ひ", "", "へ", "ほ"]),
+    ("hy", ["ひゃ", "", "ひゅ", "ひぇ", "ひょ"]),
+    ("f", ["ふぁ", "ふぃ", "ふ", "ふぇ", "ふぉ"]),
+    ("b", ["ば", "び", "ぶ", "べ", "ぼ"]),
+    ("by", ["びゃ", "", "びゅ", "びぇ", "びょ"]),
+    ("p", ["ぱ", "ぴ", "ぷ", "ぺ", "ぽ"]),
+    ("py", ["ぴゃ", "", "ぴゅ", "ぴぇ", "ぴょ"]),
+    ("m", ["ま", "み", "む", "め", "も"]),
+    ("my", ["みゃ", "", "みゅ", "みぇ", "みょ"]),
+    ("y", ["や", "", "ゆ", "いぇ", "よ"]),
+    ("r", ["ら", "り", "る", "れ", "ろ"]),
+    ("ry", ["りゃ", "", "りゅ", "りぇ", "りょ"]),
+    ("ky", ["きゃ", "", "きゅ", "きぇ", "きょ"]),
+    ("gy", ["ぎゃ", "", "ぎゅ", "ぎぇ", "ぎょ"]),
+    ("w", ["わ", "うぃ", "", "うぇ", "を"]),
+    ("v", ["ゔぁ", "ゔぃ", "ゔ", "ゔぇ", "ゔぉ"]),
+];
+
+const VOWELS: [&str; 5] = ["a", "i", "u", "e", "o"];
+
+// Kunrei spellings people type, only accepted as input
+const ROMAJI_ALTERNATIVES: &[(&str, &str)] = &[
+    ("si", "し"),
+    ("zi", "じ"),
+    ("tu", "つ"),
+    ("hu", "ふ"),
+    ("sya", "しゃ"),
+    ("syu", "しゅ"),
+    ("syo", "しょ"),
+    ("tya", "ちゃ"),
+    ("tyu", "ちゅ"),
+    ("tyo", "ちょ"),
+    ("zya", "じゃ"),
+    ("zyu", "じゅ"),
+    ("zyo", "じょ"),
+    ("nn", "ん"),
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct KanaEntry {
+    pub hiragana: String,
+    pub katakana: String,
+    pub romaji: String,
+}
+
+// katakana shares the hiragana layout 0x60 code points higher
+pub fn to_hiragana(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            'ァ'..='ヶ' => char::from_u32(c as u32 - 0x60).unwrap_or(c),
+            _ => c,
+        })
+        .collect()
+}
+
+pub fn to_katakana(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            'ぁ'..='ゖ' => char::from_u32(c as u32 + 0x60).unwrap_or(c),
+            _ => c,
+        })
+        .collect()
+}
+
+// every syllable of the table, ん included
+pub fn kana_table() -> Vec<KanaEntry> {
+    let mut table: Vec<KanaEntry> = KANA_ROWS
+        .iter()
+        .flat_map(|(consonant, row)| {
+            row.iter()
+                .zip(VOWELS)
+                .filter(|(kana, _)| !kana.is_empty())
+                .map(move |(kana, vowel)| KanaEntry {
+                    hiragana: kana.to_string(),
+                    katakana: to_katakana(kana),
+                    romaji: format!("{}{}", consonant, vowel),
+                })
+        })
+        .collect();
+    table.push(KanaEntry {
+        hiragana: "ん".to_string(),
+        katakana: "ン".to_string(),
+        romaji: "n".to_string(),
+    });
+    table
+}
+
+pub fn romaji_to_hiragana(romaji: &str) -> Option<String> {
+    let romaji = romaji.trim().to_ascii_lowercase();
+    if romaji == "n" {
+        return Some("ん".to_string());
+    }
+    if let Some((_, kana)) = ROMAJI_ALTERNATIVES.iter().find(|(r, _)| *r == romaji) {
+        return Some(kana.to_string());
+    }
+    KANA_ROWS.iter().find_map(|(consonant, row)| {
+        let vowel = romaji.strip_prefix(consonant)?;
+        let v = VOWELS.iter().position(|v| *v == vowel)?;
+        Some(row[v])
+            .filter(|kana| !kana.is_empty())
+            .map(|k| k.to_string())
+    })
+}
+
+// (consonant, vowel) in romaji, "n" is its own vowel
+pub fn split_syllable(lyric: &str) -> Option<(String, String)> {
+    let lyric = lyric.trim();
+    let kana = if lyric.is_ascii() {
+        romaji_to_hiragana(lyric)?
+    } else {
+        to_hiragana(lyric)
+    };
+    if kana == "ん" {
+        return Some((String::new(), "n".to_string()));
+    }
+
+    KANA_ROWS.iter().find_map(|(consonant, row)| {
+        row.iter()
+            .position(|k| !k.is_empty() && *k == kana)
+            .map(|v| (consonant.to_string(), VOWELS[v].to_string()))
+    })
+}
+
+// the other spellings of an alias, converting only its last word so that the
+// vowel in VCV aliases like "a ka" stays as it is
+pub fn alias_variants(alias: &str) -> Vec<String> {
+    let (head, syllable) = match alias.rsplit_once(' ') {
+        Some((head, syllable)) => (format!("{} ", head), syllable),
+        None => (String::new(), alias),
+    };
+    let Some((consonant, vowel)) = split_syllable(syllable) else {
+        return Vec::new();
+    };
+    let hiragana = if syllable.is_ascii() {
+        romaji_to_hiragana(syllable).unwrap_or_default()
+    } else {
+        to_hiragana(syllable)
+    };
+    let romaji = if vowel == "n" {
+        "n".to_string()
+    } else {
+        format!("{}{}", consonant, vowel)
+    };
+
+    let mut variants = Vec::new();
+    for form in [hiragana.clone(), to_katakana(&hiragana), romaji] {
+        let variant = format!("{}{}", head, form);
+        if variant != alias && !variants.contains(&variant) {
+            variants.push(variant);
+        }
+    }
+    variants
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{alias_variants, kana_table, romaji_to_hiragana, split_syllable};
+
+    #[test]
+    fn test_split_syllable() {
+        assert_eq!(split_syllable("か"), Some(("k".into(), "a".into())));
+        assert_eq!(split_syllable("キャ"), Some(("ky".into(), "a".into())));
+        assert_eq!(split_syllable("shi"), Some(("sh".into(), "i".into())));
+        assert_eq!(split_syllable("ん"), Some(("".into(), "n".into())));
+        assert_eq!(split_syllable("xyz"), None);
+    }
+
+    #[test]
+    fn test_alias_variants() {
+        assert_eq!(alias_variants("ka"), vec!["か", "カ"]);
+        assert_eq!(alias_variants("a シャ"), vec!["a しゃ", "a sha"]);
+        assert_eq!(alias_variants("si"), vec!["し", "シ", "shi"]);
+        assert!(alias_variants("breath").is_empty());
+        assert_eq!(romaji_to_hiragana("vu").as_deref(), Some("ゔ"));
+        assert!(
+            kana_table()
+                .iter()
+                .any(|k| k.katakana == "ヴァ" && k.romaji == "va")
+        );
+    }
+}
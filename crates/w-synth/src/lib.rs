@@ -1,31 +1,61 @@
-use bincode::config;
-use serde::Deserialize;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::ffi::CStr;
 use std::os::raw::c_char;
 use std::ptr;
 
+mod archive;
 mod audio;
+mod auto_oto;
+mod batch;
 mod consts;
+mod curve;
+mod encoding;
+mod expression;
 mod filter;
 mod flags;
+mod frq;
 mod interpolator;
+mod kana;
 mod oto;
+mod pack;
+mod parallel;
 mod parser;
+mod phonemizer;
+mod pitch;
 mod resample;
 mod util;
+mod voicebank;
 mod world;
-use oto::{OtoMap, PrefixMap, parse_oto_ini, parse_prefix_map};
+use archive::read_voicebank_archive;
+use auto_oto::{SampleStyle, estimate_oto_entry};
+use batch::AnalysisResult;
+use curve::Curve;
+use expression::Expressions;
+use frq::{FrqData, parse_frq};
+use oto::{
+    OtoDocument, OtoEntry, OtoMap, PrefixMap, VoiceColor, VoiceColors, insert_oto_entry,
+    parse_oto_ini, parse_prefix_map, read_oto_ini, validate_oto_ini, write_oto_ini,
+};
+use pack::{FeaturePack, decode_pack, encode_pack};
+use phonemizer::{PhonemizerPreset, phonemize};
+use pitch::{PitchShape, Portamento, Vibrato, deserialize_vibrato};
 use std::panic;
-use world::features::{WorldFeatures, generate_features};
+use voicebank::{VoicebankFiles, VoicebankInfo, parse_voicebank_info};
+use world::cache::{CacheExpectation, CacheStatus, Compression, decode_features, encode_features};
+use world::compact::{CachedFeatures, FeatureStorage};
+use world::features::{AnalysisConfig, generate_features};
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 struct PitchbendPointInfo {
     offset: f64,
     value: f64,
+    // curve from this point to the next
+    #[serde(default)]
+    shape: PitchShape,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 struct NoteInfo {
     alias: String,
     pitch: i32,
@@ -36,26 +66,167 @@ struct NoteInfo {
     velocity: f64,
     volume: f64,
     modulation: f64,
+    // name of a voice color, None uses the bank's default prefix.map
+    #[serde(default)]
+    voice_color: Option<String>,
+    // an object or a UST VBR string
+    #[serde(default, deserialize_with = "deserialize_vibrato")]
+    vibrato: Option<Vibrato>,
+}
+
+impl NoteInfo {
+    // notes moved by the phonemizer can be off by float rounding
+    fn touches(&self, next: &NoteInfo) -> bool {
+        (self.start_time + self.duration - next.start_time).abs() < 1e-6
+    }
 }
 
 #[derive(Deserialize, Debug)]
 struct ProjectInfo {
     notes: Vec<NoteInfo>,
     tempo: f64,
+    // turns plain lyrics into the bank's VCV/CV/CVVC aliases
+    #[serde(default)]
+    phonemizer: Option<PhonemizerPreset>,
+    // automatic glide between adjacent notes, null turns it off
+    #[serde(default = "default_portamento")]
+    portamento: Option<Portamento>,
+    // pitch deviation in cents on top of every note's own bends
+    #[serde(default)]
+    pitch_curve: Curve,
+    // dynamics, breathiness, gender, tension and brightness over absolute time
+    #[serde(default)]
+    expressions: Expressions,
+}
+
+fn default_portamento() -> Option<Portamento> {
+    Some(Portamento::default())
+}
+
+// returned by wsynth_engine_load_zip
+#[derive(Serialize)]
+struct ArchiveSummary {
+    root: String,
+    name_encoding: &'static str,
+    oto_files: usize,
+    oto_entries: usize,
+    samples: usize,
+    // samples taken from straycat-rs feature files instead of being analyzed
+    imported: usize,
+    analyzed: usize,
+}
+
+struct NoteJob<'a> {
+    note: &'a NoteInfo,
+    oto_entry: &'a OtoEntry,
+    prev_note_overlap_ms: f64,
+    has_next_note_for_crossfade: bool,
+    // previous note's pitch relative to this one, when it glides in from it
+    portamento_interval: Option<f64>,
 }
 
 pub struct WSynthEngine {
     oto: OtoMap,
-    features_cache: HashMap<String, WorldFeatures>,
+    oto_files: Vec<OtoDocument>,
+    features_cache: HashMap<String, CachedFeatures>,
+    feature_storage: FeatureStorage,
+    pending_wavs: Vec<(String, Vec<u8>)>,
+    // frequency maps for queued wavs, keyed like pending_wavs
+    pending_frqs: HashMap<String, FrqData>,
     prefix_map: PrefixMap,
+    voice_colors: VoiceColors,
+    voicebank_info: Option<VoicebankInfo>,
 }
 
 impl WSynthEngine {
     pub fn new() -> Self {
         Self {
             oto: HashMap::new(),
+            oto_files: Vec::new(),
             features_cache: HashMap::new(),
-            prefix_map: HashMap::new(),
+            feature_storage: FeatureStorage::Full,
+            pending_wavs: Vec::new(),
+            pending_frqs: HashMap::new(),
+            prefix_map: PrefixMap::new(),
+            voice_colors: VoiceColors::new(),
+            voicebank_info: None,
+        }
+    }
+
+    fn clear_voicebank(&mut self) {
+        self.oto.clear();
+        self.oto_files.clear();
+        self.prefix_map.clear();
+        self.voice_colors.clear();
+        self.voicebank_info = None;
+        self.features_cache.clear();
+        self.pending_wavs.clear();
+        self.pending_frqs.clear();
+    }
+
+    fn add_oto(&mut self, dir: &str, data: &[u8]) -> bool {
+        match read_oto_ini(std::io::Cursor::new(data), dir, &mut self.oto) {
+            Ok(document) => {
+                let count = document.entry_count();
+                if !document.bad_lines.is_empty() {
+                    println!(
+                        "[wsynth-rust] oto.ini in '{}' read as {}, lines {:?} have invalid bytes.",
+                        dir,
+                        document.encoding_name(),
+                        document.bad_lines
+                    );
+                }
+                self.oto_files.push(document);
+                count > 0
+            }
+            Err(e) => {
+                println!("[wsynth-rust] oto.ini error in '{}': {}", dir, e);
+                false
+            }
+        }
+    }
+
+    fn cache_analysis(&mut self, results: Vec<AnalysisResult>) -> usize {
+        let mut analyzed = 0;
+        for (filename, result) in results {
+            match result {
+                Ok(features) => {
+                    let cached = CachedFeatures::new(features, self.feature_storage);
+                    self.features_cache.insert(filename, cached);
+                    analyzed += 1;
+                }
+                Err(e) => println!("[wsynth-rust] Analysis error for '{}': {}", filename, e),
+            }
+        }
+        analyzed
+    }
+
+    // analyzed samples with their length, None for ones still waiting for analysis
+    fn sample_lengths(&self) -> HashMap<String, Option<f64>> {
+        let mut sample_lengths: HashMap<String, Option<f64>> = self
+            .features_cache
+            .iter()
+            .map(|(filename, features)| (filename.clone(), Some(features.length_ms())))
+            .collect();
+        for (filename, _) in &self.pending_wavs {
+            sample_lengths.entry(filename.clone()).or_insert(None);
+        }
+        sample_lengths
+    }
+
+    fn resolve_alias(&self, note: &NoteInfo) -> String {
+        let color = note.voice_color.as_deref().and_then(|name| {
+            let color = self.voice_colors.get(name);
+            if color.is_none() {
+                println!("[wsynth-rust] Unknown voice color '{}'", name);
+            }
+            color
+        });
+        match color {
+            Some(color) => {
+                resample::resolve_alias(note, &color.prefix_map, &self.oto, color.dir.as_deref())
+            }
+            None => resample::resolve_alias(note, &self.prefix_map, &self.oto, None),
         }
     }
 }
@@ -97,14 +268,219 @@ pub unsafe extern "C" fn wsynth_engine_load_oto(
     let data_slice = unsafe { std::slice::from_raw_parts(data, len) };
 
     match parse_oto_ini(std::io::Cursor::new(data_slice)) {
-        Ok(oto_map) => {
+        Ok((oto_map, document)) => {
             engine.oto = oto_map;
+            engine.oto_files = vec![document];
             true
         }
         Err(_) => false,
     }
 }
 #[unsafe(no_mangle)]
+pub unsafe extern "C" fn wsynth_engine_add_oto(
+    engine_ptr: *mut WSynthEngine,
+    dir_ptr: *const c_char,
+    data: *const u8,
+    len: usize,
+) -> bool {
+    if engine_ptr.is_null() {
+        return false;
+    }
+    let engine = unsafe { &mut *engine_ptr };
+    let dir = if dir_ptr.is_null() {
+        ""
+    } else {
+        unsafe { CStr::from_ptr(dir_ptr).to_str().unwrap_or("") }
+    };
+    let data_slice = unsafe { std::slice::from_raw_parts(data, len) };
+    engine.add_oto(dir, data_slice)
+}
+// returns the issues as a JSON array, checked against the samples the engine knows
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wsynth_engine_validate_oto(
+    engine_ptr: *mut WSynthEngine,
+    dir_ptr: *const c_char,
+    data: *const u8,
+    len: usize,
+) -> *mut WasmBuffer {
+    if engine_ptr.is_null() {
+        return ptr::null_mut();
+    }
+    let engine = unsafe { &*engine_ptr };
+    let dir = if dir_ptr.is_null() {
+        ""
+    } else {
+        unsafe { CStr::from_ptr(dir_ptr).to_str().unwrap_or("") }
+    };
+    let data_slice = unsafe { std::slice::from_raw_parts(data, len) };
+
+    match validate_oto_ini(
+        std::io::Cursor::new(data_slice),
+        dir,
+        &engine.sample_lengths(),
+    )
+    .and_then(|issues| Ok(serde_json::to_vec(&issues)?))
+    {
+        Ok(json) => into_wasm_buffer(json),
+        Err(e) => {
+            println!(
+                "[wsynth-rust] oto.ini validation failed in '{}': {}",
+                dir, e
+            );
+            ptr::null_mut()
+        }
+    }
+}
+// entry_json is an OtoEntry, matched by alias; the filename stays as loaded
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wsynth_engine_update_oto(
+    engine_ptr: *mut WSynthEngine,
+    entry_json_ptr: *const c_char,
+) -> bool {
+    if engine_ptr.is_null() || entry_json_ptr.is_null() {
+        return false;
+    }
+    let engine = unsafe { &mut *engine_ptr };
+    let entry_json = match unsafe { CStr::from_ptr(entry_json_ptr).to_str() } {
+        Ok(s) => s,
+        Err(_) => return false,
+    };
+    let update: OtoEntry = match serde_json::from_str(entry_json) {
+        Ok(entry) => entry,
+        Err(e) => {
+            println!("[wsynth-rust] Invalid oto entry: {}", e);
+            return false;
+        }
+    };
+    let values = [
+        update.offset,
+        update.consonant,
+        update.cutoff,
+        update.preutterance,
+        update.overlap,
+    ];
+    if values.iter().any(|v| !v.is_finite()) {
+        return false;
+    }
+
+    match engine.oto.get_mut(&update.alias) {
+        Some(entry) => {
+            entry.offset = update.offset;
+            entry.consonant = update.consonant;
+            entry.cutoff = update.cutoff;
+            entry.preutterance = update.preutterance;
+            entry.overlap = update.overlap;
+            true
+        }
+        None => {
+            println!("[wsynth-rust] Alias '{}' is not in the oto", update.alias);
+            false
+        }
+    }
+}
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wsynth_engine_write_oto(
+    engine_ptr: *mut WSynthEngine,
+    dir_ptr: *const c_char,
+) -> *mut WasmBuffer {
+    if engine_ptr.is_null() {
+        return ptr::null_mut();
+    }
+    let engine = unsafe { &*engine_ptr };
+    let dir = if dir_ptr.is_null() {
+        ""
+    } else {
+        unsafe { CStr::from_ptr(dir_ptr).to_str().unwrap_or("") }
+    };
+
+    let Some(document) = engine.oto_files.iter().find(|d| d.dir == dir) else {
+        println!("[wsynth-rust] No oto.ini was loaded from '{}'", dir);
+        return ptr::null_mut();
+    };
+    let mut bytes = Vec::new();
+    match write_oto_ini(document, &engine.oto, &mut bytes) {
+        Ok(()) => into_wasm_buffer(bytes),
+        Err(e) => {
+            println!("[wsynth-rust] Failed to write oto.ini for '{}': {}", dir, e);
+            ptr::null_mut()
+        }
+    }
+}
+// drafts entries for analyzed samples without one, adds them to the engine and
+// returns them as a JSON array; style is a SampleStyle code
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wsynth_engine_auto_oto(
+    engine_ptr: *mut WSynthEngine,
+    style: i32,
+) -> *mut WasmBuffer {
+    if engine_ptr.is_null() {
+        return ptr::null_mut();
+    }
+    let engine = unsafe { &mut *engine_ptr };
+    let Some(style) = SampleStyle::from_code(style) else {
+        return ptr::null_mut();
+    };
+
+    let covered: HashSet<&str> = engine.oto.values().map(|e| e.filename.as_str()).collect();
+    let mut missing: Vec<&String> = engine
+        .features_cache
+        .keys()
+        .filter(|filename| !covered.contains(filename.as_str()))
+        .collect();
+    missing.sort();
+    let drafts: Vec<OtoEntry> = missing
+        .into_iter()
+        .filter_map(|filename| {
+            let features = engine.features_cache[filename].features();
+            let entry = estimate_oto_entry(filename, &features, style);
+            if entry.is_none() {
+                println!("[wsynth-rust] No sound found in '{}', skipped", filename);
+            }
+            entry
+        })
+        .collect();
+
+    let mut added = Vec::with_capacity(drafts.len());
+    for mut entry in drafts {
+        let (dir, name) = entry
+            .filename
+            .rsplit_once('/')
+            .map_or((String::new(), entry.filename.clone()), |(d, n)| {
+                (d.to_string(), n.to_string())
+            });
+        let alias = entry.alias.clone();
+        let key = insert_oto_entry(&mut engine.oto, entry.clone());
+        entry.alias = key.clone();
+
+        match engine
+            .oto_files
+            .iter_mut()
+            .find(|document| document.normalized_dir() == dir)
+        {
+            Some(document) => document.push_entry(key, &name, &alias),
+            None => {
+                let mut document = OtoDocument::new(&dir);
+                document.push_entry(key, &name, &alias);
+                engine.oto_files.push(document);
+            }
+        }
+        added.push(entry);
+    }
+
+    match serde_json::to_vec(&added) {
+        Ok(json) => into_wasm_buffer(json),
+        Err(_) => ptr::null_mut(),
+    }
+}
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wsynth_engine_clear_voicebank(engine_ptr: *mut WSynthEngine) {
+    if engine_ptr.is_null() {
+        return;
+    }
+    let engine = unsafe { &mut *engine_ptr };
+    engine.clear_voicebank();
+}
+#[unsafe(no_mangle)]
 pub unsafe extern "C" fn wsynth_engine_load_prefix_map(
     engine_ptr: *mut WSynthEngine,
     data: *const u8,
@@ -124,31 +500,386 @@ pub unsafe extern "C" fn wsynth_engine_load_prefix_map(
         Err(_) => false,
     }
 }
+// dir and the prefix.map data are both optional, a color needs at least one
 #[unsafe(no_mangle)]
-pub unsafe extern "C" fn wsynth_analyze_wav(data: *const u8, len: usize) -> *mut WasmBuffer {
-    let data_slice = unsafe { std::slice::from_raw_parts(data, len) };
+pub unsafe extern "C" fn wsynth_engine_add_voice_color(
+    engine_ptr: *mut WSynthEngine,
+    name_ptr: *const c_char,
+    dir_ptr: *const c_char,
+    data: *const u8,
+    len: usize,
+) -> bool {
+    if engine_ptr.is_null() || name_ptr.is_null() {
+        return false;
+    }
+    let engine = unsafe { &mut *engine_ptr };
+    let name = match unsafe { CStr::from_ptr(name_ptr).to_str() } {
+        Ok(s) if !s.is_empty() => s.to_string(),
+        _ => return false,
+    };
+    let dir = if dir_ptr.is_null() {
+        None
+    } else {
+        unsafe { CStr::from_ptr(dir_ptr).to_str().ok() }
+            .filter(|d| !d.is_empty())
+            .map(|d| d.to_string())
+    };
+
+    let prefix_map = if data.is_null() || len == 0 {
+        PrefixMap::new()
+    } else {
+        let data_slice = unsafe { std::slice::from_raw_parts(data, len) };
+        match parse_prefix_map(std::io::Cursor::new(data_slice)) {
+            Ok(prefix_map) => prefix_map,
+            Err(e) => {
+                println!("[wsynth-rust] prefix.map error in color '{}': {}", name, e);
+                return false;
+            }
+        }
+    };
+    if dir.is_none() && prefix_map.rules().is_empty() {
+        return false;
+    }
+
+    engine
+        .voice_colors
+        .insert(name, VoiceColor { prefix_map, dir });
+    true
+}
+unsafe fn optional_slice<'a>(data: *const u8, len: usize) -> Option<&'a [u8]> {
+    if data.is_null() || len == 0 {
+        None
+    } else {
+        Some(unsafe { std::slice::from_raw_parts(data, len) })
+    }
+}
+// every file is optional, pass null for the ones the bank doesn't have;
+// character.yaml subbanks are registered as voice colors
+#[unsafe(no_mangle)]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn wsynth_engine_load_voicebank_info(
+    engine_ptr: *mut WSynthEngine,
+    character_txt: *const u8,
+    character_txt_len: usize,
+    character_yaml: *const u8,
+    character_yaml_len: usize,
+    readme_txt: *const u8,
+    readme_txt_len: usize,
+    install_txt: *const u8,
+    install_txt_len: usize,
+) -> bool {
+    if engine_ptr.is_null() {
+        return false;
+    }
+    let engine = unsafe { &mut *engine_ptr };
+    let files = VoicebankFiles {
+        character_txt: unsafe { optional_slice(character_txt, character_txt_len) },
+        character_yaml: unsafe { optional_slice(character_yaml, character_yaml_len) },
+        readme_txt: unsafe { optional_slice(readme_txt, readme_txt_len) },
+        install_txt: unsafe { optional_slice(install_txt, install_txt_len) },
+    };
 
+    match parse_voicebank_info(&files) {
+        Ok(info) => {
+            engine.voice_colors.extend(info.voice_colors());
+            engine.voicebank_info = Some(info);
+            true
+        }
+        Err(e) => {
+            println!("[wsynth-rust] Voicebank metadata error: {}", e);
+            false
+        }
+    }
+}
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wsynth_engine_voicebank_info(
+    engine_ptr: *mut WSynthEngine,
+) -> *mut WasmBuffer {
+    if engine_ptr.is_null() {
+        return ptr::null_mut();
+    }
+    let engine = unsafe { &*engine_ptr };
+    let Some(info) = &engine.voicebank_info else {
+        return ptr::null_mut();
+    };
+    match serde_json::to_vec(info) {
+        Ok(json) => into_wasm_buffer(json),
+        Err(_) => ptr::null_mut(),
+    }
+}
+fn into_wasm_buffer(mut bytes: Vec<u8>) -> *mut WasmBuffer {
+    bytes.shrink_to_fit();
+    let ptr = bytes.as_mut_ptr();
+    let len = bytes.len();
+    std::mem::forget(bytes);
+
+    let buffer = Box::new(WasmBuffer { ptr, len });
+    Box::into_raw(buffer)
+}
+
+fn analyze_wav(
+    data_slice: &[u8],
+    config: &AnalysisConfig,
+    frq: Option<&FrqData>,
+) -> *mut WasmBuffer {
     let audio = match audio::read_write::read_audio(data_slice) {
         Ok(a) => a,
         Err(_) => return ptr::null_mut(),
     };
 
-    match generate_features(audio, None) {
+    match generate_features(audio, config, frq) {
         Ok(features) => {
-            let bincode_config = config::standard();
-            match bincode::encode_to_vec(&features, bincode_config) {
-                Ok(mut serialized_bytes) => {
-                    serialized_bytes.shrink_to_fit();
-                    let ptr = serialized_bytes.as_mut_ptr();
-                    let len = serialized_bytes.len();
-                    std::mem::forget(serialized_bytes);
-
-                    let buffer = Box::new(WasmBuffer { ptr, len });
-                    Box::into_raw(buffer)
-                }
+            let source_hash = util::fnv1a_64(data_slice);
+            match encode_features(&features, source_hash, Compression::Deflate) {
+                Ok(serialized_bytes) => into_wasm_buffer(serialized_bytes),
                 Err(_) => std::ptr::null_mut(),
             }
         }
+        Err(e) => {
+            println!("[wsynth-rust] Analysis error: {}", e);
+            ptr::null_mut()
+        }
+    }
+}
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wsynth_analyze_wav(data: *const u8, len: usize) -> *mut WasmBuffer {
+    let data_slice = unsafe { std::slice::from_raw_parts(data, len) };
+    analyze_wav(data_slice, &AnalysisConfig::default(), None)
+}
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wsynth_analyze_wav_with_config(
+    data: *const u8,
+    len: usize,
+    config_json_ptr: *const c_char,
+) -> *mut WasmBuffer {
+    let data_slice = unsafe { std::slice::from_raw_parts(data, len) };
+    match unsafe { parse_analysis_config(config_json_ptr) } {
+        Some(config) => analyze_wav(data_slice, &config, None),
+        None => ptr::null_mut(),
+    }
+}
+// like wsynth_analyze_wav_with_config, F0 comes from the .frq data when the config sets use_frq
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wsynth_analyze_wav_with_frq(
+    data: *const u8,
+    len: usize,
+    frq_data: *const u8,
+    frq_len: usize,
+    config_json_ptr: *const c_char,
+) -> *mut WasmBuffer {
+    let data_slice = unsafe { std::slice::from_raw_parts(data, len) };
+    let frq = match unsafe { optional_slice(frq_data, frq_len) }.map(parse_frq) {
+        Some(Ok(frq)) => Some(frq),
+        Some(Err(e)) => {
+            println!("[wsynth-rust] frq error: {}", e);
+            None
+        }
+        None => None,
+    };
+    match unsafe { parse_analysis_config(config_json_ptr) } {
+        Some(config) => analyze_wav(data_slice, &config, frq.as_ref()),
+        None => ptr::null_mut(),
+    }
+}
+unsafe fn parse_analysis_config(config_json_ptr: *const c_char) -> Option<AnalysisConfig> {
+    if config_json_ptr.is_null() {
+        return Some(AnalysisConfig::default());
+    }
+    let json_str = unsafe { CStr::from_ptr(config_json_ptr).to_str().unwrap_or("") };
+    match serde_json::from_str(json_str) {
+        Ok(c) => Some(c),
+        Err(e) => {
+            println!("[wsynth-rust] Analysis config parse error: {}", e);
+            None
+        }
+    }
+}
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wsynth_engine_queue_wav(
+    engine_ptr: *mut WSynthEngine,
+    filename_ptr: *const c_char,
+    data: *const u8,
+    len: usize,
+) -> bool {
+    if engine_ptr.is_null() {
+        return false;
+    }
+    let engine = unsafe { &mut *engine_ptr };
+    let filename = unsafe { CStr::from_ptr(filename_ptr).to_str().unwrap_or("") };
+    if filename.is_empty() {
+        return false;
+    }
+
+    let data_slice = unsafe { std::slice::from_raw_parts(data, len) };
+    engine
+        .pending_wavs
+        .push((filename.to_string(), data_slice.to_vec()));
+    true
+}
+// the frq is used for the queued wav with the same filename
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wsynth_engine_queue_frq(
+    engine_ptr: *mut WSynthEngine,
+    filename_ptr: *const c_char,
+    data: *const u8,
+    len: usize,
+) -> bool {
+    if engine_ptr.is_null() || filename_ptr.is_null() {
+        return false;
+    }
+    let engine = unsafe { &mut *engine_ptr };
+    let filename = unsafe { CStr::from_ptr(filename_ptr).to_str().unwrap_or("") };
+    if filename.is_empty() {
+        return false;
+    }
+
+    let data_slice = unsafe { std::slice::from_raw_parts(data, len) };
+    match parse_frq(data_slice) {
+        Ok(frq) => {
+            engine.pending_frqs.insert(filename.to_string(), frq);
+            true
+        }
+        Err(e) => {
+            println!("[wsynth-rust] frq error for '{}': {}", filename, e);
+            false
+        }
+    }
+}
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wsynth_engine_analyze_queued(
+    engine_ptr: *mut WSynthEngine,
+    config_json_ptr: *const c_char,
+    threads: u32,
+) -> i32 {
+    if engine_ptr.is_null() {
+        return -1;
+    }
+    let engine = unsafe { &mut *engine_ptr };
+    let config = match unsafe { parse_analysis_config(config_json_ptr) } {
+        Some(c) => c,
+        None => return -1,
+    };
+
+    let files = std::mem::take(&mut engine.pending_wavs);
+    let frqs = std::mem::take(&mut engine.pending_frqs);
+    let results = batch::analyze_batch(&files, &config, &frqs, threads as usize);
+    engine.cache_analysis(results) as i32
+}
+// replaces the loaded voicebank with the one in a zip: oto.ini files, prefix.map,
+// character.txt/yaml and every wav, analyzed with the given config; returns a JSON summary
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wsynth_engine_load_zip(
+    engine_ptr: *mut WSynthEngine,
+    data: *const u8,
+    len: usize,
+    config_json_ptr: *const c_char,
+    threads: u32,
+) -> *mut WasmBuffer {
+    if engine_ptr.is_null() || data.is_null() {
+        return ptr::null_mut();
+    }
+    let engine = unsafe { &mut *engine_ptr };
+    let Some(config) = (unsafe { parse_analysis_config(config_json_ptr) }) else {
+        return ptr::null_mut();
+    };
+    let data_slice = unsafe { std::slice::from_raw_parts(data, len) };
+    let mut archive = match read_voicebank_archive(data_slice) {
+        Ok(archive) => archive,
+        Err(e) => {
+            println!("[wsynth-rust] Voicebank archive error: {}", e);
+            return ptr::null_mut();
+        }
+    };
+
+    engine.clear_voicebank();
+    for (dir, oto_data) in &archive.oto_files {
+        engine.add_oto(dir, oto_data);
+    }
+    if engine.oto.is_empty() {
+        println!("[wsynth-rust] No oto.ini entries could be read from the archive.");
+        return ptr::null_mut();
+    }
+    if let Some(prefix_map_data) = &archive.prefix_map {
+        match parse_prefix_map(std::io::Cursor::new(prefix_map_data)) {
+            Ok(prefix_map) => engine.prefix_map = prefix_map,
+            Err(e) => println!("[wsynth-rust] prefix.map error: {}", e),
+        }
+    }
+    match parse_voicebank_info(&archive.metadata_files()) {
+        Ok(info) => {
+            engine.voice_colors.extend(info.voice_colors());
+            engine.voicebank_info = Some(info);
+        }
+        Err(e) => println!("[wsynth-rust] Voicebank metadata error: {}", e),
+    }
+
+    let expectation = CacheExpectation {
+        config: Some(&config),
+        source_hash: None,
+    };
+    let mut imported = 0;
+    for (sample_path, feature_data) in &archive.features {
+        match decode_features(feature_data, &expectation) {
+            Ok(features) => {
+                let cached = CachedFeatures::new(features, engine.feature_storage);
+                engine.features_cache.insert(sample_path.clone(), cached);
+                imported += 1;
+            }
+            Err(e) => println!(
+                "[wsynth-rust] Feature file for '{}' ignored: {}",
+                sample_path, e
+            ),
+        }
+    }
+    let samples = archive.wavs.len();
+    archive
+        .wavs
+        .retain(|(path, _)| !engine.features_cache.contains_key(path));
+
+    let frqs: HashMap<String, FrqData> = archive
+        .frqs
+        .iter()
+        .filter_map(|(sample_path, frq_data)| match parse_frq(frq_data) {
+            Ok(frq) => Some((sample_path.clone(), frq)),
+            Err(e) => {
+                println!("[wsynth-rust] frq for '{}' ignored: {}", sample_path, e);
+                None
+            }
+        })
+        .collect();
+    let results = batch::analyze_batch(&archive.wavs, &config, &frqs, threads as usize);
+    let analyzed = engine.cache_analysis(results);
+
+    // every sample length is known now, so the bank's oto problems are reported once here
+    let sample_lengths = engine.sample_lengths();
+    for (dir, oto_data) in &archive.oto_files {
+        match validate_oto_ini(std::io::Cursor::new(oto_data), dir, &sample_lengths) {
+            Ok(issues) => {
+                for issue in issues {
+                    println!(
+                        "[wsynth-rust] oto.ini in '{}', line {}: {}",
+                        dir, issue.line, issue.message
+                    );
+                }
+            }
+            Err(e) => println!(
+                "[wsynth-rust] oto.ini validation failed in '{}': {}",
+                dir, e
+            ),
+        }
+    }
+
+    let summary = ArchiveSummary {
+        root: archive.root.clone(),
+        name_encoding: archive.name_encoding.name(),
+        oto_files: archive.oto_files.len(),
+        oto_entries: engine.oto.len(),
+        samples,
+        imported,
+        analyzed,
+    };
+    match serde_json::to_vec(&summary) {
+        Ok(json) => into_wasm_buffer(json),
         Err(_) => ptr::null_mut(),
     }
 }
@@ -169,14 +900,164 @@ pub unsafe extern "C" fn wsynth_engine_cache_features(
     }
 
     let data_slice = unsafe { std::slice::from_raw_parts(data, len) };
-    let bincode_config = config::standard();
 
-    match bincode::decode_from_slice::<WorldFeatures, _>(data_slice, bincode_config) {
-        Ok((features, _)) => {
-            engine.features_cache.insert(filename.to_string(), features);
+    match decode_features(data_slice, &CacheExpectation::default()) {
+        Ok(features) => {
+            let cached = CachedFeatures::new(features, engine.feature_storage);
+            engine.features_cache.insert(filename.to_string(), cached);
             true
         }
-        Err(_) => false,
+        Err(e) => {
+            println!(
+                "[wsynth-rust] Feature cache for '{}' rejected: {}",
+                filename, e
+            );
+            false
+        }
+    }
+}
+// replaces the whole bank; packs carry no voice colors or character files, so
+// register those again with wsynth_engine_load_voicebank_info afterwards
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wsynth_engine_load_pack(
+    engine_ptr: *mut WSynthEngine,
+    data: *const u8,
+    len: usize,
+) -> bool {
+    if engine_ptr.is_null() {
+        return false;
+    }
+    let engine = unsafe { &mut *engine_ptr };
+    let data_slice = unsafe { std::slice::from_raw_parts(data, len) };
+
+    match decode_pack(data_slice) {
+        Ok(pack) => {
+            // nothing of the previous bank may survive; without oto.ini layouts this bank
+            // can't be saved back
+            engine.clear_voicebank();
+            engine.oto = pack.oto_map();
+            engine.prefix_map = pack.prefix_map();
+            engine.features_cache = pack
+                .features
+                .into_iter()
+                .map(|(filename, features)| {
+                    (
+                        filename,
+                        CachedFeatures::new(features, engine.feature_storage),
+                    )
+                })
+                .collect();
+            true
+        }
+        Err(e) => {
+            println!("[wsynth-rust] Feature pack rejected: {}", e);
+            false
+        }
+    }
+}
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wsynth_engine_save_pack(engine_ptr: *mut WSynthEngine) -> *mut WasmBuffer {
+    if engine_ptr.is_null() {
+        return ptr::null_mut();
+    }
+    let engine = unsafe { &*engine_ptr };
+    let features = engine
+        .features_cache
+        .iter()
+        .map(|(filename, cached)| (filename.clone(), cached.features().into_owned()))
+        .collect();
+    let pack = FeaturePack::new(&engine.oto, &engine.prefix_map, features);
+
+    match encode_pack(&pack) {
+        Ok(bytes) => into_wasm_buffer(bytes),
+        Err(_) => ptr::null_mut(),
+    }
+}
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wsynth_engine_set_feature_storage(
+    engine_ptr: *mut WSynthEngine,
+    storage_code: i32,
+) -> bool {
+    if engine_ptr.is_null() {
+        return false;
+    }
+    let engine = unsafe { &mut *engine_ptr };
+    let storage = match FeatureStorage::from_code(storage_code) {
+        Some(s) => s,
+        None => return false,
+    };
+
+    engine.feature_storage = storage;
+    for cached in engine.features_cache.values_mut() {
+        *cached = CachedFeatures::new(cached.features().into_owned(), storage);
+    }
+    true
+}
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wsynth_check_feature_cache(
+    data: *const u8,
+    len: usize,
+    wav_data: *const u8,
+    wav_len: usize,
+    config_json_ptr: *const c_char,
+) -> i32 {
+    let data_slice = unsafe { std::slice::from_raw_parts(data, len) };
+    let source_hash = if wav_data.is_null() {
+        None
+    } else {
+        let wav_slice = unsafe { std::slice::from_raw_parts(wav_data, wav_len) };
+        Some(util::fnv1a_64(wav_slice))
+    };
+    let config = if config_json_ptr.is_null() {
+        None
+    } else {
+        match unsafe { parse_analysis_config(config_json_ptr) } {
+            Some(c) => Some(c),
+            None => return CacheStatus::ConfigMismatch as i32,
+        }
+    };
+    let expectation = CacheExpectation {
+        config: config.as_ref(),
+        source_hash,
+    };
+
+    match decode_features(data_slice, &expectation) {
+        Ok(_) => CacheStatus::Ok as i32,
+        Err(e) => e.status() as i32,
+    }
+}
+// hiragana/katakana/romaji rows as a JSON array
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wsynth_kana_table() -> *mut WasmBuffer {
+    match serde_json::to_vec(&kana::kana_table()) {
+        Ok(json) => into_wasm_buffer(json),
+        Err(_) => ptr::null_mut(),
+    }
+}
+// the oto alias a NoteInfo would be rendered with, null if the bank has none
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wsynth_engine_resolve_alias(
+    engine_ptr: *mut WSynthEngine,
+    note_json_ptr: *const c_char,
+) -> *mut WasmBuffer {
+    if engine_ptr.is_null() || note_json_ptr.is_null() {
+        return ptr::null_mut();
+    }
+    let engine = unsafe { &*engine_ptr };
+    let note_json = unsafe { CStr::from_ptr(note_json_ptr).to_str().unwrap_or("") };
+    let note: NoteInfo = match serde_json::from_str(note_json) {
+        Ok(note) => note,
+        Err(e) => {
+            println!("[wsynth-rust] JSON parse error: {}", e);
+            return ptr::null_mut();
+        }
+    };
+
+    let alias = engine.resolve_alias(&note);
+    if engine.oto.contains_key(&alias) {
+        into_wasm_buffer(alias.into_bytes())
+    } else {
+        ptr::null_mut()
     }
 }
 #[unsafe(no_mangle)]
@@ -190,13 +1071,18 @@ pub unsafe extern "C" fn wsynth_engine_synthesize_project(
     let engine = unsafe { &*engine_ptr };
     let json_str = unsafe { CStr::from_ptr(json_str_ptr).to_str().unwrap_or("") };
 
-    let project: ProjectInfo = match serde_json::from_str(json_str) {
+    let mut project: ProjectInfo = match serde_json::from_str(json_str) {
         Ok(p) => p,
         Err(e) => {
             println!("[wsynth-rust] JSON parse error: {}", e);
             return ptr::null_mut();
         }
     };
+    if let Some(preset) = project.phonemizer {
+        project.notes = phonemize(&project.notes, preset, |note| {
+            engine.oto.get(&engine.resolve_alias(note)).cloned()
+        });
+    }
 
     if project.notes.is_empty() {
         return ptr::null_mut();
@@ -207,29 +1093,35 @@ pub unsafe extern "C" fn wsynth_engine_synthesize_project(
     let total_samples = (total_duration_ms / 1000.0 * consts::SAMPLE_RATE as f64).ceil() as usize;
     let mut master_buffer = vec![0.0f64; total_samples];
 
+    // resolve everything sequentially, render in parallel, then mix in note order
+    // so the result matches a sequential render exactly
+    let mut jobs: Vec<NoteJob> = Vec::with_capacity(project.notes.len());
     for i in 0..project.notes.len() {
         let current_note = &project.notes[i];
 
         let mut prev_note_overlap_ms = 0.0;
-        let has_prev_note_for_crossfade = if i > 0 {
+        let mut portamento_interval = None;
+        if i > 0 {
             let prev_note = &project.notes[i - 1];
-            if (prev_note.start_time + prev_note.duration) == current_note.start_time {
-                let prev_alias =
-                    resample::resolve_alias(prev_note, &engine.prefix_map, &engine.oto);
+            if prev_note.touches(current_note) {
+                let prev_alias = engine.resolve_alias(prev_note);
                 if let Some(prev_oto) = engine.oto.get(&prev_alias) {
                     prev_note_overlap_ms = prev_oto.overlap;
                 }
-                true
-            } else {
-                false
+                // hand-drawn bends replace the automatic glide
+                if project.portamento.is_some()
+                    && current_note.pitchbend.is_empty()
+                    && prev_note.alias.to_lowercase() != "r"
+                    && prev_note.pitch != current_note.pitch
+                {
+                    portamento_interval = Some((prev_note.pitch - current_note.pitch) as f64);
+                }
             }
-        } else {
-            false
-        };
+        }
 
         let has_next_note_for_crossfade = if i < project.notes.len() - 1 {
             let next_note = &project.notes[i + 1];
-            (current_note.start_time + current_note.duration) == next_note.start_time
+            current_note.touches(next_note)
         } else {
             false
         };
@@ -238,7 +1130,7 @@ pub unsafe extern "C" fn wsynth_engine_synthesize_project(
             continue;
         }
 
-        let final_alias = resample::resolve_alias(current_note, &engine.prefix_map, &engine.oto);
+        let final_alias = engine.resolve_alias(current_note);
         let oto_entry = match engine.oto.get(&final_alias) {
             Some(entry) => entry,
             None => {
@@ -250,16 +1142,31 @@ pub unsafe extern "C" fn wsynth_engine_synthesize_project(
             }
         };
 
-        match resample::render_note(
-            current_note,
+        jobs.push(NoteJob {
+            note: current_note,
             oto_entry,
-            &engine.features_cache,
-            project.tempo,
             prev_note_overlap_ms,
             has_next_note_for_crossfade,
-        ) {
+            portamento_interval,
+        });
+    }
+
+    let rendered = parallel::map_ordered(&jobs, 0, |job| {
+        resample::render_note(
+            job.note,
+            job.oto_entry,
+            &engine.features_cache,
+            &project,
+            job.prev_note_overlap_ms,
+            job.has_next_note_for_crossfade,
+            job.portamento_interval,
+        )
+    });
+
+    for (job, result) in jobs.iter().zip(rendered) {
+        match result {
             Ok(rendered_pcm) => {
-                let pcm_start_ms = current_note.start_time - oto_entry.preutterance;
+                let pcm_start_ms = job.note.start_time - job.oto_entry.preutterance;
                 let start_sample =
                     (pcm_start_ms / 1000.0 * consts::SAMPLE_RATE as f64).round() as isize;
 
@@ -275,7 +1182,7 @@ pub unsafe extern "C" fn wsynth_engine_synthesize_project(
             Err(e) => {
                 println!(
                     "[wsynth-rust] Render error for note '{}': {}",
-                    current_note.alias, e
+                    job.note.alias, e
                 );
             }
         }
@@ -290,15 +1197,7 @@ pub unsafe extern "C" fn wsynth_engine_synthesize_project(
     }
 
     match audio::read_write::write_audio(&master_buffer) {
-        Ok(mut wav_bytes) => {
-            wav_bytes.shrink_to_fit();
-            let ptr = wav_bytes.as_mut_ptr();
-            let len = wav_bytes.len();
-            std::mem::forget(wav_bytes);
-
-            let buffer = Box::new(WasmBuffer { ptr, len });
-            Box::into_raw(buffer)
-        }
+        Ok(wav_bytes) => into_wasm_buffer(wav_bytes),
         Err(_) => ptr::null_mut(),
     }
 }
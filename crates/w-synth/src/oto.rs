@@ -1,9 +1,12 @@
+use crate::encoding::{decode_lines, encode_text};
 use anyhow::{Result, anyhow};
+use bincode::{Decode, Encode};
+use encoding_rs::{Encoding, SHIFT_JIS};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::io::{BufRead, BufReader, Read};
+use std::io::{Read, Write};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, Decode, Encode)]
 pub struct OtoEntry {
     pub filename: String,
     pub alias: String,
@@ -15,73 +18,653 @@ pub struct OtoEntry {
 }
 
 pub type OtoMap = HashMap<String, OtoEntry>;
-pub type PrefixMap = HashMap<i32, String>;
 
-pub fn parse_oto_ini<R: Read>(reader: R) -> Result<OtoMap> {
-    let buffered_reader = BufReader::new(reader);
-    let mut oto_map = OtoMap::new();
+// one prefix.map row, low..=high are MIDI note numbers
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Decode, Encode)]
+pub struct PrefixRule {
+    pub low: i32,
+    pub high: i32,
+    pub prefix: String,
+    pub suffix: String,
+}
+
+#[derive(Debug, Clone, Default, Decode, Encode)]
+pub struct PrefixMap {
+    rules: Vec<PrefixRule>,
+}
+
+impl PrefixMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // later rows override earlier ones for the pitches they cover
+    pub fn insert(&mut self, rule: PrefixRule) {
+        self.rules.push(rule);
+    }
+
+    pub fn clear(&mut self) {
+        self.rules.clear();
+    }
+
+    pub fn rules(&self) -> &[PrefixRule] {
+        &self.rules
+    }
+
+    // a row covering the pitch wins, otherwise the closest single note below it,
+    // which is how the old NOTE=suffix files were applied
+    pub fn find(&self, pitch: i32) -> Option<&PrefixRule> {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| rule.low <= pitch && pitch <= rule.high)
+            .or_else(|| {
+                self.rules
+                    .iter()
+                    .filter(|rule| rule.low == rule.high && rule.low <= pitch)
+                    .max_by_key(|rule| rule.low)
+            })
+    }
+}
+
+// oto filenames are relative to the oto.ini's folder, keys are relative to the bank root
+pub fn join_sample_path(dir: &str, filename: &str) -> String {
+    let dir = dir.replace('\\', "/");
+    let filename = filename.trim().replace('\\', "/");
+    let dir = dir.trim_matches('/');
+    if dir.is_empty() {
+        filename
+    } else {
+        format!("{}/{}", dir, filename.trim_start_matches('/'))
+    }
+}
+
+// UTAU falls back to the file name without extension for empty aliases
+pub fn alias_from_filename(filename: &str) -> String {
+    let name = filename.rsplit(['/', '\\']).next().unwrap_or(filename);
+    match name.rfind('.') {
+        Some(dot) if dot > 0 => name[..dot].to_string(),
+        _ => name.to_string(),
+    }
+}
+
+// duplicates are kept as numbered variants: a, a2, a3...
+pub fn insert_oto_entry(oto_map: &mut OtoMap, mut entry: OtoEntry) -> String {
+    let mut alias = entry.alias.clone();
+    let mut n = 2;
+    while oto_map.contains_key(&alias) {
+        alias = format!("{}{}", entry.alias, n);
+        n += 1;
+    }
+    entry.alias = alias.clone();
+    oto_map.insert(alias.clone(), entry);
+    alias
+}
+
+struct OtoLine<'a> {
+    filename: &'a str,
+    alias_raw: &'a str,
+    alias: String,
+    params: Vec<&'a str>,
+}
+
+fn split_oto_line(line: &str) -> Option<OtoLine<'_>> {
+    let eq_pos = line.find('=')?;
+    let filename = line[..eq_pos].trim();
+    let mut params_iter = line[eq_pos + 1..].split(',');
+    let alias_raw = params_iter.next()?.trim();
+    let alias = match alias_raw {
+        "" => alias_from_filename(filename),
+        alias => alias.to_string(),
+    };
+
+    Some(OtoLine {
+        filename,
+        alias_raw,
+        alias,
+        params: params_iter.map(|p| p.trim()).collect(),
+    })
+}
+
+#[derive(Debug, Clone)]
+enum OtoSourceLine {
+    // comments, blank and unusable lines are written back untouched
+    Raw(String),
+    Entry {
+        key: String,
+        filename: String,
+        alias: String,
+    },
+}
+
+// layout of one oto.ini as it was loaded, so edits can be saved without reordering it
+#[derive(Debug, Clone)]
+pub struct OtoDocument {
+    pub dir: String,
+    lines: Vec<OtoSourceLine>,
+    line_ending: &'static str,
+    // saved back in the encoding it was read with
+    encoding: &'static Encoding,
+    bom: bool,
+    // 1-based lines that weren't valid in the detected encoding
+    pub bad_lines: Vec<usize>,
+}
+
+impl OtoDocument {
+    pub fn new(dir: &str) -> Self {
+        Self {
+            dir: dir.to_string(),
+            lines: Vec::new(),
+            line_ending: "\r\n",
+            encoding: SHIFT_JIS,
+            bom: false,
+            bad_lines: Vec::new(),
+        }
+    }
+
+    // dir as a bank-relative path, whatever separators the loader used
+    pub fn normalized_dir(&self) -> String {
+        self.dir.replace('\\', "/").trim_matches('/').to_string()
+    }
+
+    // filename is relative to the document's folder
+    pub fn push_entry(&mut self, key: String, filename: &str, alias: &str) {
+        self.lines.push(OtoSourceLine::Entry {
+            key,
+            filename: filename.to_string(),
+            alias: alias.to_string(),
+        });
+    }
+
+    pub fn encoding_name(&self) -> &'static str {
+        self.encoding.name()
+    }
+
+    pub fn entry_count(&self) -> usize {
+        self.lines
+            .iter()
+            .filter(|line| matches!(line, OtoSourceLine::Entry { .. }))
+            .count()
+    }
+}
+
+pub fn read_oto_ini<R: Read>(
+    mut reader: R,
+    dir: &str,
+    oto_map: &mut OtoMap,
+) -> Result<OtoDocument> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    let decoded = decode_lines(&bytes);
+    let mut lines = Vec::new();
+
+    for line in &decoded.lines {
+        let Some(oto_line) = split_oto_line(line).filter(|l| l.params.len() >= 5) else {
+            lines.push(OtoSourceLine::Raw(line.to_string()));
+            continue;
+        };
+        let params = &oto_line.params;
+
+        let entry = OtoEntry {
+            filename: join_sample_path(dir, oto_line.filename),
+            alias: oto_line.alias.clone(),
+            offset: params[0].parse().unwrap_or(0.0),
+            consonant: params[1].parse().unwrap_or(0.0),
+            cutoff: params[2].parse().unwrap_or(0.0),
+            preutterance: params[3].parse().unwrap_or(0.0),
+            overlap: params[4].parse().unwrap_or(0.0),
+        };
+        lines.push(OtoSourceLine::Entry {
+            key: insert_oto_entry(oto_map, entry),
+            filename: oto_line.filename.to_string(),
+            alias: oto_line.alias_raw.to_string(),
+        });
+    }
+
+    Ok(OtoDocument {
+        dir: dir.to_string(),
+        lines,
+        line_ending: decoded.line_ending,
+        encoding: decoded.encoding,
+        bom: decoded.bom,
+        bad_lines: decoded.bad_lines,
+    })
+}
+
+fn format_oto_value(value: f64) -> String {
+    let rounded = (value * 1000.0).round() / 1000.0;
+    // avoid writing "-0"
+    format!("{}", rounded + 0.0)
+}
+
+// entries removed from the map since loading are dropped from the file
+pub fn write_oto_ini<W: Write>(
+    document: &OtoDocument,
+    oto_map: &OtoMap,
+    mut writer: W,
+) -> Result<()> {
+    let mut text = String::new();
+    for line in &document.lines {
+        match line {
+            OtoSourceLine::Raw(raw) => text.push_str(raw),
+            OtoSourceLine::Entry {
+                key,
+                filename,
+                alias,
+            } => {
+                let Some(entry) = oto_map.get(key) else {
+                    continue;
+                };
+                text.push_str(&format!(
+                    "{}={},{},{},{},{},{}",
+                    filename,
+                    alias,
+                    format_oto_value(entry.offset),
+                    format_oto_value(entry.consonant),
+                    format_oto_value(entry.cutoff),
+                    format_oto_value(entry.preutterance),
+                    format_oto_value(entry.overlap)
+                ));
+            }
+        }
+        text.push_str(document.line_ending);
+    }
+    writer.write_all(&encode_text(&text, document.encoding, document.bom))?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OtoSeverity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OtoIssueKind {
+    Malformed,
+    MissingParams,
+    InvalidNumber,
+    ConsonantBeforePreutterance,
+    OverlapAfterPreutterance,
+    CutoffBeforeConsonant,
+    CutoffPastEnd,
+    MissingWav,
+    InvalidEncoding,
+}
 
-    for line_result in buffered_reader.lines() {
-        let line = line_result?;
+impl OtoIssueKind {
+    pub fn severity(&self) -> OtoSeverity {
+        match self {
+            OtoIssueKind::ConsonantBeforePreutterance | OtoIssueKind::OverlapAfterPreutterance => {
+                OtoSeverity::Warning
+            }
+            _ => OtoSeverity::Error,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OtoIssue {
+    // 1-based, as shown by text editors
+    pub line: usize,
+    pub filename: String,
+    pub alias: String,
+    pub kind: OtoIssueKind,
+    pub severity: OtoSeverity,
+    pub message: String,
+}
+
+const OTO_PARAM_NAMES: [&str; 5] = ["offset", "consonant", "cutoff", "preutterance", "overlap"];
+
+impl OtoEntry {
+    // UTAU cutoff: negative is a length measured from the offset,
+    // positive is the blank left at the end of the file
+    pub fn end_ms(&self, sample_len_ms: f64) -> f64 {
+        if self.cutoff < 0.0 {
+            self.offset - self.cutoff
+        } else {
+            sample_len_ms - self.cutoff
+        }
+    }
+}
+
+// sample_lengths holds every known sample, None when its length isn't known yet
+pub fn validate_oto_ini<R: Read>(
+    mut reader: R,
+    dir: &str,
+    sample_lengths: &HashMap<String, Option<f64>>,
+) -> Result<Vec<OtoIssue>> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    let decoded = decode_lines(&bytes);
+    let mut issues = Vec::new();
+
+    for (index, line) in decoded.lines.iter().enumerate() {
         if line.trim().is_empty() {
             continue;
         }
 
-        if let Some(eq_pos) = line.find('=') {
-            let filename = line[..eq_pos].to_string();
-            let value_part = &line[eq_pos + 1..];
-            let mut params_iter = value_part.split(',');
+        let mut report = |filename: &str, alias: &str, kind: OtoIssueKind, message: String| {
+            issues.push(OtoIssue {
+                line: index + 1,
+                filename: filename.to_string(),
+                alias: alias.to_string(),
+                kind,
+                severity: kind.severity(),
+                message,
+            })
+        };
 
-            if let Some(alias_raw) = params_iter.next() {
-                let alias = alias_raw.trim();
-                if alias.is_empty() {
-                    continue;
-                }
+        if decoded.bad_lines.contains(&(index + 1)) {
+            report(
+                "",
+                "",
+                OtoIssueKind::InvalidEncoding,
+                format!(
+                    "Line has bytes that aren't valid {}, unreadable characters were replaced.",
+                    decoded.encoding.name()
+                ),
+            );
+        }
 
-                let params: Vec<&str> = params_iter.collect();
-                if params.len() < 5 {
-                    continue;
-                }
+        let Some(oto_line) = split_oto_line(line) else {
+            report(
+                "",
+                "",
+                OtoIssueKind::Malformed,
+                "Line has no '=' separating the file name from the parameters.".to_string(),
+            );
+            continue;
+        };
+        let filename = join_sample_path(dir, oto_line.filename);
+        let alias = oto_line.alias.as_str();
 
-                let entry = OtoEntry {
-                    filename,
-                    alias: alias.to_string(),
-                    offset: params[0].trim().parse().unwrap_or(0.0),
-                    consonant: params[1].trim().parse().unwrap_or(0.0),
-                    cutoff: params[2].trim().parse().unwrap_or(0.0),
-                    preutterance: params[3].trim().parse().unwrap_or(0.0),
-                    overlap: params[4].trim().parse().unwrap_or(0.0),
-                };
-                oto_map.insert(alias.to_string(), entry);
+        if oto_line.params.len() < 5 {
+            report(
+                &filename,
+                alias,
+                OtoIssueKind::MissingParams,
+                format!(
+                    "Expected 5 parameters after the alias, found {}. The line is ignored.",
+                    oto_line.params.len()
+                ),
+            );
+            continue;
+        }
+
+        let mut values = [0.0; 5];
+        for (i, name) in OTO_PARAM_NAMES.iter().enumerate() {
+            match oto_line.params[i].parse::<f64>() {
+                Ok(value) if value.is_finite() => values[i] = value,
+                _ => report(
+                    &filename,
+                    alias,
+                    OtoIssueKind::InvalidNumber,
+                    format!(
+                        "{} '{}' is not a number and is read as 0.",
+                        name, oto_line.params[i]
+                    ),
+                ),
+            }
+        }
+        let entry = OtoEntry {
+            filename: filename.clone(),
+            alias: alias.to_string(),
+            offset: values[0],
+            consonant: values[1],
+            cutoff: values[2],
+            preutterance: values[3],
+            overlap: values[4],
+        };
+
+        if entry.consonant < entry.preutterance {
+            report(
+                &filename,
+                alias,
+                OtoIssueKind::ConsonantBeforePreutterance,
+                format!(
+                    "Consonant ({} ms) ends before the preutterance ({} ms), the stretched part is empty.",
+                    entry.consonant, entry.preutterance
+                ),
+            );
+        }
+        if entry.overlap > entry.preutterance {
+            report(
+                &filename,
+                alias,
+                OtoIssueKind::OverlapAfterPreutterance,
+                format!(
+                    "Overlap ({} ms) is greater than the preutterance ({} ms).",
+                    entry.overlap, entry.preutterance
+                ),
+            );
+        }
+
+        match sample_lengths.get(&filename) {
+            None => report(
+                &filename,
+                alias,
+                OtoIssueKind::MissingWav,
+                format!("Sample '{}' was not found in the voicebank.", filename),
+            ),
+            Some(None) => {}
+            Some(Some(sample_len_ms)) => {
+                let end_ms = entry.end_ms(*sample_len_ms);
+                if end_ms > *sample_len_ms {
+                    report(
+                        &filename,
+                        alias,
+                        OtoIssueKind::CutoffPastEnd,
+                        format!(
+                            "Cutoff ends at {:.1} ms, past the end of the sample ({:.1} ms). Rendering stops at the end of the sample.",
+                            end_ms, sample_len_ms
+                        ),
+                    );
+                } else if end_ms < entry.offset + entry.consonant {
+                    report(
+                        &filename,
+                        alias,
+                        OtoIssueKind::CutoffBeforeConsonant,
+                        format!(
+                            "Cutoff ends at {:.1} ms, before the end of the consonant ({:.1} ms). Rendering extends it to the end of the consonant.",
+                            end_ms,
+                            entry.offset + entry.consonant
+                        ),
+                    );
+                }
             }
         }
     }
 
+    Ok(issues)
+}
+
+pub fn parse_oto_ini<R: Read>(reader: R) -> Result<(OtoMap, OtoDocument)> {
+    let mut oto_map = OtoMap::new();
+    let document = read_oto_ini(reader, "", &mut oto_map)?;
+
     if oto_map.is_empty() {
         return Err(anyhow!("Failed to read any entries from oto.ini."));
     }
-    Ok(oto_map)
+    Ok((oto_map, document))
+}
+
+// a named expression of the bank (soft, power, whisper...): its own prefix.map
+// and/or a subfolder its samples are taken from
+#[derive(Debug, Clone, Default)]
+pub struct VoiceColor {
+    pub prefix_map: PrefixMap,
+    pub dir: Option<String>,
+}
+
+pub type VoiceColors = HashMap<String, VoiceColor>;
+
+// NOTE, NOTE-NOTE or a MIDI number range like 60-71
+pub fn parse_pitch_range(text: &str) -> Option<(i32, i32)> {
+    let text = text.trim();
+    let split = text
+        .char_indices()
+        .skip(1)
+        .find(|(i, c)| *c == '-' && text[..*i].ends_with(|p: char| p.is_ascii_digit()))
+        .map(|(i, _)| i);
+
+    let (low, high) = match split {
+        Some(i) => (
+            crate::util::pitch_parser(text[..i].trim()).ok()?,
+            crate::util::pitch_parser(text[i + 1..].trim()).ok()?,
+        ),
+        None => {
+            let pitch = crate::util::pitch_parser(text).ok()?;
+            (pitch, pitch)
+        }
+    };
+    Some((low.min(high), low.max(high)))
 }
 
-pub fn parse_prefix_map<R: Read>(reader: R) -> Result<PrefixMap> {
-    let buffered_reader = BufReader::new(reader);
+// UTAU rows are NOTE\tPREFIX\tSUFFIX, older files use NOTE=SUFFIX
+pub fn parse_prefix_map<R: Read>(mut reader: R) -> Result<PrefixMap> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    let decoded = decode_lines(&bytes);
     let mut prefix_map = PrefixMap::new();
 
-    for line_result in buffered_reader.lines() {
-        let line = line_result?;
+    for (index, line) in decoded.lines.iter().enumerate() {
+        if decoded.bad_lines.contains(&(index + 1)) {
+            // a garbled prefix would never match an alias
+            println!(
+                "[wsynth-rust] prefix.map line {} isn't valid {}, skipped.",
+                index + 1,
+                decoded.encoding.name()
+            );
+            continue;
+        }
         if line.trim().is_empty() {
             continue;
         }
 
-        if let Some(eq_pos) = line.find('=') {
-            let note_str = &line[..eq_pos];
-            let suffix = line[eq_pos + 1..].to_string();
+        let (note_str, prefix, suffix) = if line.contains('\t') {
+            let mut columns = line.split('\t');
+            (
+                columns.next().unwrap_or(""),
+                columns.next().unwrap_or(""),
+                columns.next().unwrap_or(""),
+            )
+        } else if let Some(eq_pos) = line.find('=') {
+            (&line[..eq_pos], "", &line[eq_pos + 1..])
+        } else {
+            continue;
+        };
 
-            if let Ok(pitch) = crate::util::pitch_parser(note_str) {
-                prefix_map.insert(pitch, suffix);
-            }
+        if let Some((low, high)) = parse_pitch_range(note_str) {
+            prefix_map.insert(PrefixRule {
+                low,
+                high,
+                prefix: prefix.to_string(),
+                suffix: suffix.to_string(),
+            });
         }
     }
 
     Ok(prefix_map)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        OtoIssueKind, OtoMap, parse_prefix_map, read_oto_ini, validate_oto_ini, write_oto_ini,
+    };
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_oto_dirs_and_aliases() {
+        let root =
+            "a.wav=a,0,100,-200,50,20\na.wav=a,10,100,-200,50,20\n_ka.wav=,5,100,-200,50,20\n";
+        let sub = "ka.wav=- ka,0,100,-200,50,20\nka.wav=a,0,100,-200,50,20\n";
+
+        let mut oto = OtoMap::new();
+        assert_eq!(
+            read_oto_ini(root.as_bytes(), "", &mut oto)
+                .unwrap()
+                .entry_count(),
+            3
+        );
+        assert_eq!(
+            read_oto_ini(sub.as_bytes(), "C4\\", &mut oto)
+                .unwrap()
+                .entry_count(),
+            2
+        );
+
+        assert_eq!(oto["a"].offset, 0.);
+        assert_eq!(oto["a2"].offset, 10.);
+        assert_eq!(oto["a3"].filename, "C4/ka.wav");
+        assert_eq!(oto["_ka"].filename, "_ka.wav");
+        assert_eq!(oto["- ka"].filename, "C4/ka.wav");
+    }
+
+    #[test]
+    fn test_oto_validation() {
+        let oto = "a.wav=a,0,100,-200,50,20\n\
+                   a.wav=i,0,40,-200,50,60\n\
+                   a.wav=u,0,100,-900,50,20\n\
+                   a.wav=e,x,100,-200,50,20\n\
+                   a.wav=o,0,100\n\
+                   missing.wav=n,0,100,-200,50,20\n\
+                   a.wav=ka,0,100,-50,50,20\n";
+        let mut lengths = HashMap::new();
+        lengths.insert("a.wav".to_string(), Some(500.));
+
+        let issues = validate_oto_ini(oto.as_bytes(), "", &lengths).unwrap();
+        let kinds: Vec<(usize, OtoIssueKind)> = issues.iter().map(|i| (i.line, i.kind)).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (2, OtoIssueKind::ConsonantBeforePreutterance),
+                (2, OtoIssueKind::OverlapAfterPreutterance),
+                (3, OtoIssueKind::CutoffPastEnd),
+                (4, OtoIssueKind::InvalidNumber),
+                (5, OtoIssueKind::MissingParams),
+                (6, OtoIssueKind::MissingWav),
+                (7, OtoIssueKind::CutoffBeforeConsonant),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_oto_write_keeps_layout() {
+        let source = "; tuned by hand\r\nb.wav=,0,100,-200,50,20\r\na.wav=a,10.5,100,-200,50,20\r\nbroken line\r\na.wav=a,20,100,-200,50,20\r\n";
+        let mut oto = OtoMap::new();
+        let document = read_oto_ini(source.as_bytes(), "sub", &mut oto).unwrap();
+        assert_eq!(document.entry_count(), 3);
+
+        oto.get_mut("a2").unwrap().offset = 25.25;
+        let mut written = Vec::new();
+        write_oto_ini(&document, &oto, &mut written).unwrap();
+
+        assert_eq!(
+            String::from_utf8(written).unwrap(),
+            "; tuned by hand\r\nb.wav=,0,100,-200,50,20\r\na.wav=a,10.5,100,-200,50,20\r\nbroken line\r\na.wav=a,25.25,100,-200,50,20\r\n"
+        );
+    }
+
+    #[test]
+    fn test_prefix_map_columns_and_ranges() {
+        let utau = "C4\t\u{2191}\t_H\nB3\t\t_L\nC5-B5\tx\t\n";
+        let prefix_map = parse_prefix_map(utau.as_bytes()).unwrap();
+        let rule = prefix_map.find(60).unwrap();
+        assert_eq!(
+            (rule.prefix.as_str(), rule.suffix.as_str()),
+            ("\u{2191}", "_H")
+        );
+        assert_eq!(prefix_map.find(59).unwrap().suffix, "_L");
+        assert_eq!(prefix_map.find(75).unwrap().prefix, "x");
+        assert_eq!(prefix_map.find(65).unwrap().suffix, "_H");
+        assert!(prefix_map.find(40).is_none());
+
+        let legacy = parse_prefix_map("C4=_C4\nC-1=_low\n".as_bytes()).unwrap();
+        assert_eq!(legacy.find(62).unwrap().suffix, "_C4");
+        assert_eq!(legacy.find(0).unwrap().suffix, "_low");
+    }
+}
@@ -0,0 +1,134 @@
+use crate::oto::{OtoEntry, OtoMap, PrefixMap, PrefixRule};
+use crate::world::cache::{CACHE_VERSION, CacheError};
+use crate::world::features::WorldFeatures;
+use anyhow::Result;
+use bincode::{Decode, Encode, config};
+use flate2::Compression;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use std::io::Read;
+
+pub const PACK_MAGIC: &[u8; 4] = b"WSFP";
+
+// entries are sorted so the same bank always produces the same pack; voice colors
+// and voicebank metadata aren't stored
+#[derive(Debug, Decode, Encode)]
+pub struct FeaturePack {
+    pub oto: Vec<OtoEntry>,
+    pub prefix_map: Vec<PrefixRule>,
+    pub features: Vec<(String, WorldFeatures)>,
+}
+
+impl FeaturePack {
+    pub fn new(
+        oto_map: &OtoMap,
+        prefix_map: &PrefixMap,
+        mut features: Vec<(String, WorldFeatures)>,
+    ) -> Self {
+        let mut oto: Vec<OtoEntry> = oto_map.values().cloned().collect();
+        oto.sort_by(|a, b| a.alias.cmp(&b.alias));
+        // row order matters for overlapping ranges, so it is kept as loaded
+        let prefix_map = prefix_map.rules().to_vec();
+        features.sort_by(|a, b| a.0.cmp(&b.0));
+
+        Self {
+            oto,
+            prefix_map,
+            features,
+        }
+    }
+
+    pub fn oto_map(&self) -> OtoMap {
+        self.oto
+            .iter()
+            .map(|entry| (entry.alias.clone(), entry.clone()))
+            .collect()
+    }
+
+    pub fn prefix_map(&self) -> PrefixMap {
+        let mut prefix_map = PrefixMap::new();
+        for rule in &self.prefix_map {
+            prefix_map.insert(rule.clone());
+        }
+        prefix_map
+    }
+}
+
+// layout: magic, version, deflated bincode FeaturePack
+pub fn encode_pack(pack: &FeaturePack) -> Result<Vec<u8>> {
+    let bincode_config = config::standard();
+    let mut bytes = PACK_MAGIC.to_vec();
+    bytes.extend(bincode::encode_to_vec(CACHE_VERSION, bincode_config)?);
+
+    let mut encoder = DeflateEncoder::new(bytes, Compression::default());
+    bincode::encode_into_std_write(pack, &mut encoder, bincode_config)?;
+    Ok(encoder.finish()?)
+}
+
+pub fn decode_pack(bytes: &[u8]) -> Result<FeaturePack, CacheError> {
+    if bytes.len() < PACK_MAGIC.len() || &bytes[..PACK_MAGIC.len()] != PACK_MAGIC {
+        return Err(CacheError::BadMagic);
+    }
+    let body = &bytes[PACK_MAGIC.len()..];
+
+    let (version, read) = bincode::decode_from_slice::<u16, _>(body, config::standard())
+        .map_err(|e| CacheError::Corrupt(e.to_string()))?;
+    if version != CACHE_VERSION {
+        return Err(CacheError::VersionMismatch { found: version });
+    }
+
+    let mut payload = Vec::new();
+    DeflateDecoder::new(&body[read..])
+        .read_to_end(&mut payload)
+        .map_err(|e| CacheError::Corrupt(e.to_string()))?;
+    let (pack, _) = bincode::decode_from_slice::<FeaturePack, _>(&payload, config::standard())
+        .map_err(|e| CacheError::Corrupt(e.to_string()))?;
+    Ok(pack)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FeaturePack, decode_pack, encode_pack};
+    use crate::oto::{OtoEntry, OtoMap, PrefixMap, PrefixRule};
+    use crate::world::features::{AnalysisConfig, WorldFeatures};
+
+    #[test]
+    fn test_pack_roundtrip() {
+        let mut oto = OtoMap::new();
+        oto.insert(
+            "- ka".to_string(),
+            OtoEntry {
+                filename: "ka.wav".to_string(),
+                alias: "- ka".to_string(),
+                offset: 100.,
+                consonant: 120.,
+                cutoff: -300.,
+                preutterance: 80.,
+                overlap: 20.,
+            },
+        );
+        let mut prefix_map = PrefixMap::new();
+        prefix_map.insert(PrefixRule {
+            low: 60,
+            high: 60,
+            prefix: String::new(),
+            suffix: "_C4".to_string(),
+        });
+        let features = WorldFeatures {
+            config: AnalysisConfig::default(),
+            base_f0: 261.6,
+            f0: vec![0., 261.6],
+            mgc: vec![vec![0.1; 3]; 2],
+            bap: vec![vec![-0.1; 2]; 2],
+        };
+
+        let pack = FeaturePack::new(&oto, &prefix_map, vec![("ka.wav".to_string(), features)]);
+        let bytes = encode_pack(&pack).unwrap();
+        let decoded = decode_pack(&bytes).unwrap();
+
+        assert_eq!(decoded.oto_map()["- ka"].offset, 100.);
+        assert_eq!(decoded.prefix_map().find(60).unwrap().suffix, "_C4");
+        assert_eq!(decoded.features[0].0, "ka.wav");
+        assert_eq!(decoded.features[0].1.f0, vec![0., 261.6]);
+    }
+}
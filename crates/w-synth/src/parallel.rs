@@ -0,0 +1,55 @@
+#[cfg(feature = "parallel")]
+use rayon::ThreadPool;
+#[cfg(feature = "parallel")]
+use std::sync::OnceLock;
+
+// built on first use; None when the platform can't spawn threads
+// (emscripten without pthreads)
+#[cfg(feature = "parallel")]
+static POOL: OnceLock<Option<ThreadPool>> = OnceLock::new();
+
+// Order-preserving parallel map. threads == 0 uses every core, otherwise the
+// items are split into at most `threads` chunks. Runs on the current thread
+// when no pool could be built, so callers never have to care.
+#[cfg(feature = "parallel")]
+pub fn map_ordered<T, R, F>(items: &[T], threads: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync + Send,
+{
+    use rayon::prelude::*;
+
+    let pool = POOL.get_or_init(|| rayon::ThreadPoolBuilder::new().build().ok());
+    match pool {
+        Some(pool) => {
+            let min_len = if threads == 0 {
+                1
+            } else {
+                items.len().div_ceil(threads).max(1)
+            };
+            pool.install(|| items.par_iter().with_min_len(min_len).map(&f).collect())
+        }
+        None => items.iter().map(f).collect(),
+    }
+}
+
+#[cfg(not(feature = "parallel"))]
+pub fn map_ordered<T, R, F>(items: &[T], _threads: usize, f: F) -> Vec<R>
+where
+    F: Fn(&T) -> R,
+{
+    items.iter().map(f).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::map_ordered;
+
+    #[test]
+    fn test_map_ordered() {
+        let items: Vec<u64> = (0..1000).collect();
+        let sequential: Vec<u64> = items.iter().map(|x| x * x).collect();
+        assert_eq!(map_ordered(&items, 4, |x| x * x), sequential);
+    }
+}
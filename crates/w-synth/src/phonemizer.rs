@@ -0,0 +1,164 @@
+use crate::NoteInfo;
+use crate::kana::split_syllable;
+use crate::oto::OtoEntry;
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PhonemizerPreset {
+    Japanese,
+}
+
+fn is_rest(lyric: &str) -> bool {
+    matches!(lyric.trim(), "" | "r" | "R")
+}
+
+// explicit aliases such as "a ka" or "- ka" are left alone
+fn is_explicit_alias(lyric: &str) -> bool {
+    lyric.trim().contains(' ')
+}
+
+const MIN_VC_MS: f64 = 30.;
+
+// rewrites lyrics into VCV, CV or CVVC aliases; lookup resolves a note against the
+// bank (prefix.map and voice color included) and returns the entry if it exists
+pub fn phonemize<F>(notes: &[NoteInfo], preset: PhonemizerPreset, lookup: F) -> Vec<NoteInfo>
+where
+    F: Fn(&NoteInfo) -> Option<OtoEntry>,
+{
+    let PhonemizerPreset::Japanese = preset;
+    let mut output: Vec<NoteInfo> = Vec::with_capacity(notes.len());
+    // vowel the previous note ends on, "-" at the start of a phrase
+    let mut prev_vowel: Option<String> = None;
+
+    for (i, note) in notes.iter().enumerate() {
+        let lyric = note.alias.trim();
+        let connected = i > 0 && notes[i - 1].touches(note) && !is_rest(&notes[i - 1].alias);
+        if !connected {
+            prev_vowel = Some("-".to_string());
+        }
+        if is_rest(lyric) || is_explicit_alias(lyric) {
+            output.push(note.clone());
+            prev_vowel = lyric
+                .rsplit(' ')
+                .next()
+                .and_then(split_syllable)
+                .map(|(_, vowel)| vowel);
+            continue;
+        }
+        let syllable = split_syllable(lyric);
+
+        let with_alias = |alias: String| NoteInfo {
+            alias,
+            ..note.clone()
+        };
+        let mut candidates = Vec::new();
+        if let Some(prev) = &prev_vowel {
+            candidates.push(format!("{} {}", prev, lyric));
+        }
+        candidates.push(lyric.to_string());
+
+        let chosen = candidates
+            .into_iter()
+            .map(with_alias)
+            .find_map(|candidate| lookup(&candidate).map(|entry| (candidate, entry)));
+        let Some((phonemized, entry)) = chosen else {
+            output.push(note.clone());
+            prev_vowel = syllable.map(|(_, vowel)| vowel);
+            continue;
+        };
+
+        // CVVC: a plain CV after a vowel gets a VC note cut from the end of the previous note
+        let is_cv = phonemized.alias == lyric;
+        if is_cv
+            && connected
+            && let (Some(prev), Some((consonant, _))) = (&prev_vowel, &syllable)
+            && prev != "-"
+            && !consonant.is_empty()
+            && let Some(prev_note) = output.last_mut()
+        {
+            let vc_len = entry
+                .preutterance
+                .max(MIN_VC_MS)
+                .min(prev_note.duration / 2.);
+            let vc_note = NoteInfo {
+                alias: format!("{} {}", prev, consonant),
+                start_time: note.start_time - vc_len,
+                duration: vc_len,
+                pitchbend: Vec::new(),
+                vibrato: None,
+                ..prev_note.clone()
+            };
+            if vc_len > 0. && lookup(&vc_note).is_some() {
+                prev_note.duration = vc_note.start_time - prev_note.start_time;
+                output.push(vc_note);
+            }
+        }
+
+        output.push(phonemized);
+        prev_vowel = syllable.map(|(_, vowel)| vowel);
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PhonemizerPreset, phonemize};
+    use crate::NoteInfo;
+    use crate::oto::OtoEntry;
+
+    fn note(alias: &str, start_time: f64) -> NoteInfo {
+        NoteInfo {
+            alias: alias.to_string(),
+            pitch: 60,
+            start_time,
+            duration: 500.,
+            pitchbend: Vec::new(),
+            flags: String::new(),
+            velocity: 100.,
+            volume: 100.,
+            modulation: 0.,
+            voice_color: None,
+            vibrato: None,
+        }
+    }
+
+    fn lookup_in(aliases: &'static [&'static str]) -> impl Fn(&NoteInfo) -> Option<OtoEntry> {
+        move |note| {
+            aliases.contains(&note.alias.as_str()).then(|| OtoEntry {
+                filename: String::new(),
+                alias: note.alias.clone(),
+                offset: 0.,
+                consonant: 100.,
+                cutoff: 0.,
+                preutterance: 80.,
+                overlap: 20.,
+            })
+        }
+    }
+
+    #[test]
+    fn test_vcv_and_cvvc() {
+        let notes = vec![note("か", 0.), note("さ", 500.), note("R", 1000.)];
+
+        let vcv = phonemize(
+            &notes,
+            PhonemizerPreset::Japanese,
+            lookup_in(&["- か", "a さ"]),
+        );
+        let aliases: Vec<&str> = vcv.iter().map(|n| n.alias.as_str()).collect();
+        assert_eq!(aliases, vec!["- か", "a さ", "R"]);
+
+        let cvvc = phonemize(
+            &notes,
+            PhonemizerPreset::Japanese,
+            lookup_in(&["か", "さ", "a s"]),
+        );
+        let aliases: Vec<&str> = cvvc.iter().map(|n| n.alias.as_str()).collect();
+        assert_eq!(aliases, vec!["か", "a s", "さ", "R"]);
+        assert_eq!(cvvc[0].duration, 420.);
+        assert_eq!(cvvc[1].start_time, 420.);
+        assert_eq!(cvvc[1].duration, 80.);
+    }
+}
@@ -0,0 +1,222 @@
+use serde::{Deserialize, Deserializer};
+use std::f64::consts::PI;
+
+// how a pitchbend segment moves from its point to the next one; the UST PBM letters
+// are accepted too, an empty PBM entry is UTAU's S-curve
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum PitchShape {
+    #[default]
+    #[serde(alias = "s", alias = "l")]
+    Linear,
+    #[serde(alias = "j", alias = "i")]
+    SineIn,
+    #[serde(alias = "r", alias = "o")]
+    SineOut,
+    #[serde(alias = "", alias = "io")]
+    SCurve,
+}
+
+impl PitchShape {
+    // maps 0..1 through the segment to 0..1 of the value change
+    pub fn ease(self, ratio: f64) -> f64 {
+        let ratio = ratio.clamp(0., 1.);
+        match self {
+            PitchShape::Linear => ratio,
+            PitchShape::SineIn => 1. - (ratio * PI / 2.).cos(),
+            PitchShape::SineOut => (ratio * PI / 2.).sin(),
+            PitchShape::SCurve => (1. - (ratio * PI).cos()) / 2.,
+        }
+    }
+}
+
+// glide from the previous note's pitch into a note that follows it directly
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Portamento {
+    pub length: f64,
+    // ms from the note start, negative begins inside the previous note
+    pub start: f64,
+}
+
+impl Default for Portamento {
+    // OpenUtau's defaults
+    fn default() -> Self {
+        Self {
+            length: 80.,
+            start: -40.,
+        }
+    }
+}
+
+impl Portamento {
+    // semitones to add at time_ms from the note start, coming from `interval`
+    // semitones away; S-shaped like UTAU's default pitchbend
+    pub fn semitones(&self, interval: f64, time_ms: f64) -> f64 {
+        if self.length <= 0. {
+            return 0.;
+        }
+        let ratio = (time_ms - self.start) / self.length;
+        interval * (1. - PitchShape::SCurve.ease(ratio))
+    }
+}
+
+// UTAU's VBR parameters
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Vibrato {
+    // % of the note, measured back from its end
+    pub length: f64,
+    pub period: f64,
+    pub depth: f64,
+    // % of the vibrato length
+    pub fade_in: f64,
+    pub fade_out: f64,
+    // % of a cycle
+    pub phase: f64,
+    // center shift, % of depth
+    pub offset: f64,
+    // % the frequency changes by from start to end
+    pub drift: f64,
+}
+
+impl Default for Vibrato {
+    // UTAU's defaults when vibrato is switched on
+    fn default() -> Self {
+        Self {
+            length: 65.,
+            period: 180.,
+            depth: 35.,
+            fade_in: 20.,
+            fade_out: 20.,
+            phase: 0.,
+            offset: 0.,
+            drift: 0.,
+        }
+    }
+}
+
+impl Vibrato {
+    // "length,period,depth,in,out,phase,offset,drift" as written in a UST; missing fields are 0
+    pub fn from_vbr(vbr: &str) -> Option<Self> {
+        let values: Vec<f64> = vbr
+            .split(',')
+            .map(|v| v.trim().parse::<f64>().ok())
+            .collect::<Option<_>>()?;
+        let value = |i: usize| values.get(i).copied().unwrap_or(0.);
+        Some(Self {
+            length: value(0),
+            period: value(1),
+            depth: value(2),
+            fade_in: value(3),
+            fade_out: value(4),
+            phase: value(5),
+            offset: value(6),
+            drift: value(7),
+        })
+    }
+
+    // semitones to add at time_ms into a note lasting duration_ms
+    pub fn semitones(&self, duration_ms: f64, time_ms: f64) -> f64 {
+        let length_ms = duration_ms * self.length.clamp(0., 100.) / 100.;
+        let x = time_ms - (duration_ms - length_ms);
+        if length_ms <= 0. || self.period <= 0. || x < 0. || x > length_ms {
+            return 0.;
+        }
+
+        let fade_in_ms = length_ms * self.fade_in.clamp(0., 100.) / 100.;
+        let fade_out_ms = length_ms * self.fade_out.clamp(0., 100.) / 100.;
+        let mut envelope: f64 = 1.;
+        if fade_in_ms > 0. {
+            envelope = envelope.min(x / fade_in_ms);
+        }
+        if fade_out_ms > 0. {
+            envelope = envelope.min((length_ms - x) / fade_out_ms);
+        }
+
+        // frequency moves linearly by drift %, integrated so the phase stays continuous
+        let cycles = x / self.period * (1. + self.drift / 100. * x / (2. * length_ms));
+        let angle = 2. * PI * (cycles + self.phase / 100.);
+        self.depth / 100. * envelope * (angle.sin() + self.offset / 100.)
+    }
+}
+
+// the fields as an object, or a UST VBR string passed through as is
+pub fn deserialize_vibrato<'de, D>(deserializer: D) -> Result<Option<Vibrato>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum VibratoValue {
+        Fields(Vibrato),
+        Vbr(String),
+    }
+
+    match Option::<VibratoValue>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(VibratoValue::Fields(vibrato)) => Ok(Some(vibrato)),
+        Some(VibratoValue::Vbr(vbr)) if vbr.trim().is_empty() => Ok(None),
+        Some(VibratoValue::Vbr(vbr)) => Vibrato::from_vbr(&vbr)
+            .map(Some)
+            .ok_or_else(|| serde::de::Error::custom(format!("invalid VBR value '{}'", vbr))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PitchShape, Portamento, Vibrato};
+
+    #[test]
+    fn test_vibrato() {
+        let vibrato = Vibrato::from_vbr("50,100,100,0,0,0,0").unwrap();
+        assert_eq!(vibrato.drift, 0.);
+
+        // starts halfway through a 1000 ms note, a quarter cycle later it peaks
+        assert_eq!(vibrato.semitones(1000., 400.), 0.);
+        assert!(vibrato.semitones(1000., 500.).abs() < 1e-9);
+        assert!((vibrato.semitones(1000., 525.) - 1.).abs() < 1e-9);
+        assert!((vibrato.semitones(1000., 575.) + 1.).abs() < 1e-9);
+
+        let faded = Vibrato {
+            fade_in: 50.,
+            ..vibrato.clone()
+        };
+        assert!((faded.semitones(1000., 525.) - 0.1).abs() < 1e-9);
+
+        assert!(Vibrato::from_vbr("65,180,x").is_none());
+    }
+
+    #[test]
+    fn test_pitch_shapes() {
+        let shapes: Vec<PitchShape> =
+            serde_json::from_str(r#"["", "s", "r", "j", "sine_in"]"#).unwrap();
+        assert_eq!(
+            shapes,
+            vec![
+                PitchShape::SCurve,
+                PitchShape::Linear,
+                PitchShape::SineOut,
+                PitchShape::SineIn,
+                PitchShape::SineIn
+            ]
+        );
+        for shape in shapes {
+            assert!(shape.ease(0.).abs() < 1e-9);
+            assert!((shape.ease(1.) - 1.).abs() < 1e-9);
+        }
+        assert!((PitchShape::SCurve.ease(0.5) - 0.5).abs() < 1e-9);
+        assert!(PitchShape::SineIn.ease(0.5) < 0.5);
+        assert!(PitchShape::SineOut.ease(0.5) > 0.5);
+    }
+
+    #[test]
+    fn test_portamento() {
+        let portamento = Portamento::default();
+        // from a note 2 semitones below
+        assert_eq!(portamento.semitones(-2., -100.), -2.);
+        assert!((portamento.semitones(-2., 0.) + 1.).abs() < 1e-9);
+        assert_eq!(portamento.semitones(-2., 40.), 0.);
+        assert_eq!(portamento.semitones(-2., 500.), 0.);
+    }
+}
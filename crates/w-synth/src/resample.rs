@@ -1,10 +1,11 @@
 use crate::flags::parser::Flags;
 use crate::interpolator::interp::{self, Interpolator};
+use crate::pitch::{PitchShape, Portamento};
 use crate::util::{self, smoothstep};
-use crate::world::features::WorldFeatures;
+use crate::world::compact::CachedFeatures;
 use crate::world::synthesis::{synthesize_aperiodic, synthesize_harmonic};
 use crate::{
-    consts, filter,
+    consts, filter, kana,
     oto::{OtoEntry, OtoMap, PrefixMap},
 };
 use anyhow::{Result, anyhow};
@@ -12,24 +13,31 @@ use biquad::{DirectForm2Transposed, Q_BUTTERWORTH_F64};
 use rand_distr::Distribution;
 use std::collections::HashMap;
 
-use crate::NoteInfo;
+use crate::{NoteInfo, ProjectInfo};
 
+// project-wide settings (tempo, portamento, pitch and expression curves) come from `project`
 pub fn render_note(
     current_note: &NoteInfo,
     oto_entry: &OtoEntry,
-    features_cache: &HashMap<String, WorldFeatures>,
-    tempo: f64,
+    features_cache: &HashMap<String, CachedFeatures>,
+    project: &ProjectInfo,
     prev_note_overlap_ms: f64,
     has_next_note_for_crossfade: bool,
+    portamento_interval: Option<f64>,
 ) -> Result<Vec<f64>> {
+    let portamento = project.portamento.as_ref().zip(portamento_interval);
+
     let features = features_cache
         .get(&oto_entry.filename)
-        .ok_or_else(|| anyhow!("Features for file '{}' not found", oto_entry.filename))?;
+        .ok_or_else(|| anyhow!("Features for file '{}' not found", oto_entry.filename))?
+        .features();
 
     let flags: Flags = current_note.flags.replace("/", "").parse()?;
     let volume = current_note.volume / 100.0;
 
-    let fps = 1000.0 / consts::FRAME_PERIOD;
+    let frame_period = features.config.frame_period;
+    let fft_size = features.config.fft_size;
+    let fps = 1000.0 / frame_period;
     let feature_len_sec = features.f0.len() as f64 / fps;
     let src_offset_ms = oto_entry.offset;
     let src_consonant_ms = oto_entry.consonant;
@@ -41,21 +49,19 @@ pub fn render_note(
     let src_offset_sec = src_offset_ms / 1000.0;
     let src_stretch_start_point_sec = src_offset_sec + (src_preutterance_ms / 1000.0);
     let src_stretch_end_point_sec = src_offset_sec + (src_consonant_ms / 1000.0);
-    let src_cutoff_sec = if oto_entry.cutoff >= 0.0 {
-        oto_entry.cutoff / 1000.0
-    } else {
-        feature_len_sec + (oto_entry.cutoff / 1000.0)
-    }
-    .clamp(src_stretch_end_point_sec, feature_len_sec);
+    let raw_cutoff_sec = oto_entry.end_ms(feature_len_sec * 1000.0) / 1000.0;
+    // out-of-range cutoffs are reported once by validate_oto_ini when the bank loads
+    let src_cutoff_sec = raw_cutoff_sec
+        .max(src_stretch_end_point_sec)
+        .min(feature_len_sec);
     let src_preutterance_part_len_sec = (src_stretch_start_point_sec - src_offset_sec).max(0.0);
     let src_stretch_part_len_sec =
         (src_stretch_end_point_sec - src_stretch_start_point_sec).max(0.001);
-    let src_tail_len_sec = (feature_len_sec - src_cutoff_sec).max(0.0);
     let total_render_frames = (total_render_duration_ms / 1000.0 * fps).round() as usize;
     let mut t_render: Vec<f64> = Vec::with_capacity(total_render_frames);
 
     for i in 0..total_render_frames {
-        let current_out_time_ms = i as f64 * consts::FRAME_PERIOD;
+        let current_out_time_ms = i as f64 * frame_period;
         let src_time_sec: f64;
 
         if current_out_time_ms < src_preutterance_ms {
@@ -76,7 +82,7 @@ pub fn render_note(
 
         let clamped_src_time_sec = src_time_sec.clamp(
             0.0,
-            feature_len_sec - (1.0 / fps).min(feature_len_sec.max(0.001)),
+            src_cutoff_sec - (1.0 / fps).min(src_cutoff_sec.max(0.001)),
         );
         t_render.push(clamped_src_time_sec * fps);
     }
@@ -116,95 +122,80 @@ pub fn render_note(
     );
 
     let render_length = t_render.len();
-    let mut pitch_points: Vec<(f64, f64)> = current_note
-        .pitchbend
-        .iter()
-        .map(|p| (p.offset, p.value / 100.0))
-        .collect();
-    if pitch_points.is_empty() || pitch_points.first().map_or(false, |p| p.0 > 0.0) {
-        pitch_points.insert(0, (0.0, 0.0));
-    }
-    if pitch_points
-        .last()
-        .map_or(false, |p| p.0 < current_note.duration)
-    {
-        pitch_points.push((current_note.duration, 0.0));
-    }
-    let note_duration_ms = current_note.duration;
-    let num_frames_in_note = (note_duration_ms / consts::FRAME_PERIOD).ceil() as usize;
-    if num_frames_in_note == 0 {
+    if current_note.duration <= 0.0 {
         return Ok(vec![]);
     }
-    let uniform_pitch_semitones: Vec<f64> = (0..num_frames_in_note)
-        .map(|i| {
-            let time_ms = i as f64 * consts::FRAME_PERIOD;
-            match pitch_points.binary_search_by(|(t, _)| {
-                t.partial_cmp(&time_ms).unwrap_or(std::cmp::Ordering::Equal)
-            }) {
-                Ok(i) => pitch_points[i].1,
-                Err(i) => {
-                    if i == 0 {
-                        pitch_points[0].1
-                    } else if i >= pitch_points.len() {
-                        pitch_points.last().unwrap().1
-                    } else {
-                        let (t1, p1) = pitch_points[i - 1];
-                        let (t2, p2) = pitch_points[i];
-                        let segment_duration = t2 - t1;
-                        let ratio = if segment_duration.abs() < 1e-9 {
-                            0.0
-                        } else {
-                            (time_ms - t1) / segment_duration
-                        };
-                        util::lerp(p1, p2, ratio)
-                    }
-                }
-            }
-        })
-        .collect();
+    let uniform_pitch_semitones = note_pitch_semitones(
+        current_note,
+        frame_period,
+        render_length,
+        src_preutterance_ms,
+        portamento,
+    );
     let pitch_interp = interp::Akima::new(&uniform_pitch_semitones);
     let pitch_render: Vec<f64> = (0..render_length)
         .map(|i| {
-            let time_ms = i as f64 * consts::FRAME_PERIOD;
-            let frame_pos = time_ms / consts::FRAME_PERIOD;
+            let time_ms = i as f64 * frame_period;
+            let frame_pos = time_ms / frame_period;
             pitch_interp.sample(frame_pos)
         })
         .collect();
 
+    // the project curve runs on absolute time, which covers the overlap past the note too
+    let render_start_ms = current_note.start_time - src_preutterance_ms;
     let f0_render: Vec<f64> = pitch_render
         .iter()
         .zip(vuv_render.iter())
-        .map(|(pitch, vuv)| {
+        .enumerate()
+        .map(|(i, (pitch, vuv))| {
             if *vuv {
-                util::midi_to_hz(*pitch + current_note.pitch as f64 + flags.pitch_offset / 100.0)
+                let curve_cents = project
+                    .pitch_curve
+                    .sample(render_start_ms + i as f64 * frame_period);
+                util::midi_to_hz(
+                    *pitch + current_note.pitch as f64 + (flags.pitch_offset + curve_cents) / 100.0,
+                )
             } else {
                 0.0
             }
         })
         .collect();
 
-    let feature_dim = (consts::FFT_SIZE / 2 + 1) as usize;
     let mut sp_render = rsworld::decode_spectral_envelope(
         &mgc_render,
         render_length as i32,
         consts::SAMPLE_RATE as i32,
-        consts::FFT_SIZE,
+        fft_size,
     );
-    let ap_render = rsworld::decode_aperiodicity(
+    let expressions = &project.expressions;
+    if expressions.has_spectral() {
+        for (i, frame) in sp_render.iter_mut().enumerate() {
+            expressions.apply_spectral(frame, fft_size, render_start_ms + i as f64 * frame_period);
+        }
+    }
+    let ap_render = rsworld::decode_aperiodicity_with_fft_size(
         &bap_render,
         render_length as i32,
         consts::SAMPLE_RATE as i32,
+        fft_size,
     );
 
-    let syn_harmonic: Vec<f64> = synthesize_harmonic(&f0_render, &sp_render, &ap_render);
+    let syn_harmonic: Vec<f64> =
+        synthesize_harmonic(&f0_render, &sp_render, &ap_render, frame_period);
     let syn_aperiodic: Vec<f64> =
-        synthesize_aperiodic(&f0_render, &mut sp_render, &ap_render, true);
-    let harmonic_mix = 1.0 - 2.0 * (flags.breathiness / 100.0 - 0.5);
+        synthesize_aperiodic(&f0_render, &mut sp_render, &ap_render, true, frame_period);
 
+    // breathiness and dynamics follow the project curves sample by sample
+    let ms_per_sample = 1000.0 / consts::SAMPLE_RATE as f64;
     let mut syn: Vec<f64> = syn_harmonic
         .iter()
         .zip(syn_aperiodic.iter())
-        .map(|(hm, wh)| (hm * harmonic_mix + wh) * volume)
+        .enumerate()
+        .map(|(j, (hm, wh))| {
+            let time_ms = render_start_ms + j as f64 * ms_per_sample;
+            let harmonic_mix = expressions.harmonic_mix(flags.breathiness, time_ms);
+            (hm * harmonic_mix + wh) * volume * expressions.gain(time_ms)
+        })
         .collect();
     let current_fade_in_ms = if prev_note_overlap_ms > 0.0 {
         prev_note_overlap_ms
@@ -229,6 +220,66 @@ pub fn render_note(
     Ok(syn)
 }
 
+// pitchbend, vibrato and portamento in semitones for each frame of the render,
+// which starts preutterance_ms before the note; all three run on note time, offset
+// 0 being the note start, and the first bend point holds over the preutterance
+fn note_pitch_semitones(
+    note: &NoteInfo,
+    frame_period: f64,
+    frames: usize,
+    preutterance_ms: f64,
+    portamento: Option<(&Portamento, f64)>,
+) -> Vec<f64> {
+    let mut pitch_points: Vec<(f64, f64, PitchShape)> = note
+        .pitchbend
+        .iter()
+        .map(|p| (p.offset, p.value / 100.0, p.shape))
+        .collect();
+    if pitch_points.is_empty() || pitch_points.first().map_or(false, |p| p.0 > 0.0) {
+        pitch_points.insert(0, (0.0, 0.0, PitchShape::default()));
+    }
+    if pitch_points.last().map_or(false, |p| p.0 < note.duration) {
+        pitch_points.push((note.duration, 0.0, PitchShape::default()));
+    }
+    let note_duration_ms = note.duration;
+    (0..frames)
+        .map(|i| {
+            // the note itself starts one preutterance into the render
+            let time_ms = i as f64 * frame_period - preutterance_ms;
+            let bend = match pitch_points.binary_search_by(|(t, _, _)| {
+                t.partial_cmp(&time_ms).unwrap_or(std::cmp::Ordering::Equal)
+            }) {
+                Ok(i) => pitch_points[i].1,
+                Err(i) => {
+                    if i == 0 {
+                        pitch_points[0].1
+                    } else if i >= pitch_points.len() {
+                        pitch_points.last().unwrap().1
+                    } else {
+                        let (t1, p1, shape) = pitch_points[i - 1];
+                        let (t2, p2, _) = pitch_points[i];
+                        let segment_duration = t2 - t1;
+                        let ratio = if segment_duration.abs() < 1e-9 {
+                            0.0
+                        } else {
+                            (time_ms - t1) / segment_duration
+                        };
+                        util::lerp(p1, p2, shape.ease(ratio))
+                    }
+                }
+            };
+            let vibrato = note
+                .vibrato
+                .as_ref()
+                .map_or(0.0, |vibrato| vibrato.semitones(note_duration_ms, time_ms));
+            let portamento = portamento.map_or(0.0, |(portamento, interval)| {
+                portamento.semitones(interval, time_ms)
+            });
+            bend + vibrato + portamento
+        })
+        .collect()
+}
+
 fn apply_crossfade_envelopes(
     pcm: &mut Vec<f64>,
     fade_in_len_ms: f64,
@@ -271,26 +322,44 @@ fn apply_crossfade_envelopes(
     }
 }
 
-pub fn resolve_alias(note: &NoteInfo, prefix_map: &PrefixMap, oto_map: &OtoMap) -> String {
-    if prefix_map.is_empty() {
-        return note.alias.clone();
-    }
-    let mut best_pitch_key = -1;
-    for &key in prefix_map.keys() {
-        if key <= note.pitch && key > best_pitch_key {
-            best_pitch_key = key;
-        }
-    }
+// duplicate aliases are stored as a, a2, a3..., so a subfolder can still pick its own
+fn find_alias(oto_map: &OtoMap, alias: &str, dir: Option<&str>) -> Option<String> {
+    let Some(dir) = dir else {
+        return oto_map.contains_key(alias).then(|| alias.to_string());
+    };
+    let dir = dir.replace('\\', "/");
+    let prefix = format!("{}/", dir.trim_matches('/'));
+    std::iter::once(alias.to_string())
+        .chain((2..).map(|n| format!("{}{}", alias, n)))
+        .take_while(|key| oto_map.contains_key(key))
+        .find(|key| oto_map[key].filename.starts_with(&prefix))
+}
 
-    if best_pitch_key != -1 {
-        if let Some(suffix) = prefix_map.get(&best_pitch_key) {
-            let new_alias = format!("{}{}", note.alias, suffix);
-            if oto_map.contains_key(&new_alias) {
-                return new_alias;
-            }
-        }
-    }
-    note.alias.clone()
+pub fn resolve_alias(
+    note: &NoteInfo,
+    prefix_map: &PrefixMap,
+    oto_map: &OtoMap,
+    dir: Option<&str>,
+) -> String {
+    // the lyric as typed first, then its kana/romaji spellings; any spelling in the
+    // pitch's subbank wins over the plain alias
+    let spellings: Vec<String> = std::iter::once(note.alias.clone())
+        .chain(kana::alias_variants(&note.alias))
+        .collect();
+    prefix_map
+        .find(note.pitch)
+        .and_then(|rule| {
+            spellings.iter().find_map(|alias| {
+                let new_alias = format!("{}{}{}", rule.prefix, alias, rule.suffix);
+                find_alias(oto_map, &new_alias, dir)
+            })
+        })
+        .or_else(|| {
+            spellings
+                .iter()
+                .find_map(|alias| find_alias(oto_map, alias, dir))
+        })
+        .unwrap_or_else(|| note.alias.clone())
 }
 
 fn apply_crossfade(pcm: &mut Vec<f64>, preutterance_ms: f64, overlap_ms: f64, has_prev_note: bool) {
@@ -325,3 +394,121 @@ fn apply_crossfade(pcm: &mut Vec<f64>, preutterance_ms: f64, overlap_ms: f64, ha
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{note_pitch_semitones, resolve_alias};
+    use crate::oto::{OtoEntry, OtoMap, PrefixMap, PrefixRule};
+    use crate::pitch::{PitchShape, Vibrato};
+    use crate::{NoteInfo, PitchbendPointInfo};
+
+    fn note(alias: &str, pitch: i32) -> NoteInfo {
+        NoteInfo {
+            alias: alias.to_string(),
+            pitch,
+            start_time: 0.,
+            duration: 500.,
+            pitchbend: Vec::new(),
+            flags: String::new(),
+            velocity: 100.,
+            volume: 100.,
+            modulation: 0.,
+            voice_color: None,
+            vibrato: None,
+        }
+    }
+
+    fn oto_map(aliases: &[&str]) -> OtoMap {
+        aliases
+            .iter()
+            .map(|alias| {
+                let entry = OtoEntry {
+                    filename: format!("{}.wav", alias),
+                    alias: alias.to_string(),
+                    offset: 0.,
+                    consonant: 100.,
+                    cutoff: 0.,
+                    preutterance: 80.,
+                    overlap: 20.,
+                };
+                (alias.to_string(), entry)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_resolve_alias() {
+        let mut prefix_map = PrefixMap::new();
+        prefix_map.insert(PrefixRule {
+            low: 60,
+            high: 72,
+            prefix: String::new(),
+            suffix: "↑".to_string(),
+        });
+        let oto = oto_map(&["あ↑", "a", "か"]);
+
+        // a kana spelling in the subbank beats the plain lyric as typed
+        assert_eq!(
+            resolve_alias(&note("a", 64), &prefix_map, &oto, None),
+            "あ↑"
+        );
+        // outside the rule's range the plain alias is used
+        assert_eq!(resolve_alias(&note("a", 50), &prefix_map, &oto, None), "a");
+        // no subbank entry for any spelling falls back to a plain spelling
+        assert_eq!(
+            resolve_alias(&note("ka", 64), &prefix_map, &oto, None),
+            "か"
+        );
+        assert_eq!(
+            resolve_alias(&note("xyz", 64), &prefix_map, &oto, None),
+            "xyz"
+        );
+    }
+
+    #[test]
+    fn test_vibrato_follows_the_note() {
+        let mut note = note("a", 60);
+        note.duration = 1000.;
+        // the last half of the note, full depth right away
+        note.vibrato = Vibrato::from_vbr("50,100,100,0,0,0,0");
+
+        // with 100 ms of preutterance the note, and so the vibrato, starts at frame 20
+        let semitones = note_pitch_semitones(&note, 5., 240, 100., None);
+        let at_note_ms = |ms: f64| semitones[((ms + 100.) / 5.) as usize];
+        assert_eq!(at_note_ms(450.), 0.);
+        assert!((at_note_ms(525.) - 1.).abs() < 1e-9);
+        assert!((at_note_ms(925.) - 1.).abs() < 1e-9);
+        assert_eq!(at_note_ms(1050.), 0.);
+        // one preutterance earlier is still before the vibrato window
+        assert_eq!(semitones[(525. / 5.) as usize], 0.);
+    }
+
+    #[test]
+    fn test_bends_follow_the_note() {
+        let mut note = note("a", 60);
+        note.duration = 1000.;
+        // a UST-style portamento: -2 semitones at the note start up to 0 at 100 ms
+        note.pitchbend = vec![
+            PitchbendPointInfo {
+                offset: 0.,
+                value: -200.,
+                shape: PitchShape::Linear,
+            },
+            PitchbendPointInfo {
+                offset: 100.,
+                value: 0.,
+                shape: PitchShape::Linear,
+            },
+        ];
+
+        let semitones = note_pitch_semitones(&note, 5., 240, 100., None);
+        let at_note_ms = |ms: f64| semitones[((ms + 100.) / 5.) as usize];
+        // the first point holds over the preutterance
+        assert_eq!(semitones[0], -2.);
+        assert_eq!(at_note_ms(-50.), -2.);
+        assert_eq!(at_note_ms(0.), -2.);
+        assert!((at_note_ms(50.) + 1.).abs() < 1e-9);
+        assert_eq!(at_note_ms(100.), 0.);
+        assert_eq!(at_note_ms(500.), 0.);
+    }
+}
@@ -1,4 +1,4 @@
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 use regex::Regex;
 
 pub fn arange(end: i32) -> Vec<f64> {
@@ -45,6 +45,13 @@ pub fn midi_to_hz(x: f64) -> f64 {
     440. * ((x - 69.) / 12.).exp2()
 }
 
+// FNV-1a, stable across builds unlike DefaultHasher
+pub fn fnv1a_64(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
 pub fn tempo_parser(arg: &str) -> Result<f64> {
     let tempo: f64 = arg[1..].parse()?;
     Ok(tempo)
@@ -58,9 +65,11 @@ pub fn pitch_parser(arg: &str) -> Result<i32> {
     }
 
     let note_regex = Regex::new(r"([A-G]#?)(-?\d+)")?;
-    let captures = note_regex.captures(arg).unwrap();
+    let captures = note_regex
+        .captures(arg)
+        .ok_or_else(|| anyhow!("Invalid note name: {}", arg))?;
 
-    let note = match captures.get(1).unwrap().as_str() {
+    let note = match &captures[1] {
         "C" => 0,
         "C#" => 1,
         "D" => 2,
@@ -75,7 +84,7 @@ pub fn pitch_parser(arg: &str) -> Result<i32> {
         "B" => 11,
         _ => 0,
     };
-    let octave = captures.get(2).unwrap().as_str().parse::<i32>()? + 1;
+    let octave = captures[2].parse::<i32>()? + 1;
     Ok(octave * 12 + note)
 }
 
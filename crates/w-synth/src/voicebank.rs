@@ -0,0 +1,232 @@
+use crate::encoding::decode_text;
+use crate::oto::{PrefixMap, PrefixRule, VoiceColor, VoiceColors, parse_pitch_range};
+use anyhow::Result;
+use encoding_rs::Encoding;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Subbank {
+    pub color: String,
+    pub prefix: String,
+    pub suffix: String,
+    // NOTE or NOTE-NOTE, empty covers every pitch
+    pub tone_ranges: Vec<String>,
+}
+
+// the parts of OpenUtau's character.yaml the engine cares about
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct CharacterYaml {
+    name: Option<String>,
+    image: Option<String>,
+    author: Option<String>,
+    voice: Option<String>,
+    web: Option<String>,
+    version: Option<String>,
+    sample: Option<String>,
+    portrait: Option<String>,
+    portrait_opacity: Option<f64>,
+    default_phonemizer: Option<String>,
+    singer_type: Option<String>,
+    text_file_encoding: Option<String>,
+    subbanks: Vec<Subbank>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct InstallInfo {
+    pub folder: Option<String>,
+    pub contents_dir: Option<String>,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct VoicebankInfo {
+    pub name: Option<String>,
+    pub image: Option<String>,
+    pub sample: Option<String>,
+    pub author: Option<String>,
+    pub voice: Option<String>,
+    pub web: Option<String>,
+    pub version: Option<String>,
+    pub portrait: Option<String>,
+    pub portrait_opacity: Option<f64>,
+    pub default_phonemizer: Option<String>,
+    pub singer_type: Option<String>,
+    pub subbanks: Vec<Subbank>,
+    pub readme: Option<String>,
+    pub install: Option<InstallInfo>,
+    // character.txt lines with keys the engine doesn't know
+    pub extra: Vec<(String, String)>,
+    // encoding the text files were read with
+    pub text_encoding: String,
+}
+
+// raw file contents, any of them may be missing
+#[derive(Default)]
+pub struct VoicebankFiles<'a> {
+    pub character_txt: Option<&'a [u8]>,
+    pub character_yaml: Option<&'a [u8]>,
+    pub readme_txt: Option<&'a [u8]>,
+    pub install_txt: Option<&'a [u8]>,
+}
+
+fn decode_with(bytes: &[u8], forced: Option<&'static Encoding>) -> (String, &'static Encoding) {
+    match forced {
+        Some(encoding) => {
+            let (text, encoding, _) = encoding.decode(bytes);
+            (text.into_owned(), encoding)
+        }
+        None => decode_text(bytes),
+    }
+}
+
+fn key_values(text: &str) -> impl Iterator<Item = (String, String)> + '_ {
+    text.lines().filter_map(|line| {
+        let (key, value) = line.split_once('=')?;
+        let (key, value) = (key.trim(), value.trim());
+        (!key.is_empty() && !value.is_empty()).then(|| (key.to_lowercase(), value.to_string()))
+    })
+}
+
+fn parse_install_txt(text: &str) -> InstallInfo {
+    let mut install = InstallInfo::default();
+    for (key, value) in key_values(text) {
+        match key.as_str() {
+            "folder" => install.folder = Some(value),
+            "contentsdir" => install.contents_dir = Some(value),
+            "description" => install.description = Some(value),
+            _ => {}
+        }
+    }
+    install
+}
+
+pub fn parse_voicebank_info(files: &VoicebankFiles) -> Result<VoicebankInfo> {
+    let yaml: CharacterYaml = match files.character_yaml {
+        Some(bytes) => serde_yaml::from_str(&decode_text(bytes).0)?,
+        None => CharacterYaml::default(),
+    };
+    let forced = yaml
+        .text_file_encoding
+        .as_deref()
+        .and_then(|label| Encoding::for_label(label.as_bytes()));
+
+    let mut info = VoicebankInfo::default();
+    let mut text_encoding = forced;
+
+    if let Some(bytes) = files.character_txt {
+        let (text, encoding) = decode_with(bytes, forced);
+        text_encoding = Some(encoding);
+        for (key, value) in key_values(&text) {
+            match key.as_str() {
+                "name" => info.name = Some(value),
+                "image" => info.image = Some(value),
+                "sample" => info.sample = Some(value),
+                "author" | "created by" => info.author = Some(value),
+                "web" => info.web = Some(value),
+                _ => info.extra.push((key, value)),
+            }
+        }
+    }
+    if let Some(bytes) = files.readme_txt {
+        let (text, encoding) = decode_with(bytes, forced);
+        text_encoding.get_or_insert(encoding);
+        info.readme = Some(text.trim_end().to_string());
+    }
+    if let Some(bytes) = files.install_txt {
+        let (text, encoding) = decode_with(bytes, forced);
+        text_encoding.get_or_insert(encoding);
+        info.install = Some(parse_install_txt(&text));
+    }
+
+    // character.yaml overrides character.txt where both are set
+    info.name = yaml.name.or(info.name);
+    info.image = yaml.image.or(info.image);
+    info.sample = yaml.sample.or(info.sample);
+    info.author = yaml.author.or(info.author);
+    info.web = yaml.web.or(info.web);
+    info.voice = yaml.voice;
+    info.version = yaml.version;
+    info.portrait = yaml.portrait;
+    info.portrait_opacity = yaml.portrait_opacity;
+    info.default_phonemizer = yaml.default_phonemizer;
+    info.singer_type = yaml.singer_type;
+    info.subbanks = yaml.subbanks;
+    info.text_encoding = text_encoding
+        .map_or(encoding_rs::SHIFT_JIS.name(), |e| e.name())
+        .to_string();
+
+    Ok(info)
+}
+
+impl VoicebankInfo {
+    // subbanks with a color name become voice colors, rows of one color share a prefix map
+    pub fn voice_colors(&self) -> VoiceColors {
+        let mut colors = VoiceColors::new();
+        for subbank in self.subbanks.iter().filter(|s| !s.color.is_empty()) {
+            let ranges: Vec<(i32, i32)> = if subbank.tone_ranges.is_empty() {
+                vec![(0, 127)]
+            } else {
+                subbank
+                    .tone_ranges
+                    .iter()
+                    .filter_map(|range| parse_pitch_range(range))
+                    .collect()
+            };
+
+            let color = colors
+                .entry(subbank.color.clone())
+                .or_insert_with(|| VoiceColor {
+                    prefix_map: PrefixMap::new(),
+                    dir: None,
+                });
+            for (low, high) in ranges {
+                color.prefix_map.insert(PrefixRule {
+                    low,
+                    high,
+                    prefix: subbank.prefix.clone(),
+                    suffix: subbank.suffix.clone(),
+                });
+            }
+        }
+        colors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{VoicebankFiles, parse_voicebank_info};
+    use encoding_rs::SHIFT_JIS;
+
+    #[test]
+    fn test_voicebank_info() {
+        let (character_txt, _, _) =
+            SHIFT_JIS.encode("name=重音テト\r\nimage=icon.bmp\r\nCV=someone\r\n");
+        let character_yaml = "name: Teto\nportrait: portrait.png\ndefault_phonemizer: OpenUtau.Plugin.Builtin.JapaneseVCVPhonemizer\nsubbanks:\n  - color: power\n    suffix: _P\n    tone_ranges: [C4-B5]\n  - color: soft\n    prefix: \"\u{2191}\"\n";
+        let files = VoicebankFiles {
+            character_txt: Some(&character_txt),
+            character_yaml: Some(character_yaml.as_bytes()),
+            install_txt: Some(b"type=voiceset\r\nfolder=teto\r\n"),
+            ..Default::default()
+        };
+
+        let info = parse_voicebank_info(&files).unwrap();
+        assert_eq!(info.name.as_deref(), Some("Teto"));
+        assert_eq!(info.image.as_deref(), Some("icon.bmp"));
+        assert_eq!(info.extra, vec![("cv".to_string(), "someone".to_string())]);
+        assert_eq!(info.text_encoding, "Shift_JIS");
+        assert_eq!(
+            info.install.as_ref().unwrap().folder.as_deref(),
+            Some("teto")
+        );
+
+        let colors = info.voice_colors();
+        assert_eq!(colors["power"].prefix_map.find(60).unwrap().suffix, "_P");
+        assert!(colors["power"].prefix_map.find(50).is_none());
+        assert_eq!(
+            colors["soft"].prefix_map.find(50).unwrap().prefix,
+            "\u{2191}"
+        );
+    }
+}
@@ -0,0 +1,409 @@
+use crate::consts;
+use crate::world::features::{AnalysisConfig, F0Estimator, WorldFeatures};
+use anyhow::Result;
+use bincode::{Decode, Encode, config};
+use flate2::Compression as DeflateLevel;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use std::fmt;
+use std::io::{Read, Write};
+
+pub const CACHE_MAGIC: &[u8; 4] = b"WSFC";
+// bump whenever WorldFeatures, AnalysisConfig or the pack layout change
+pub const CACHE_VERSION: u16 = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Decode, Encode)]
+pub enum Compression {
+    None,
+    Deflate,
+}
+
+#[derive(Clone, Debug, Decode, Encode)]
+pub struct CacheHeader {
+    pub version: u16,
+    pub config: AnalysisConfig,
+    pub source_hash: u64,
+    pub compression: Compression,
+}
+
+// status codes are shared with the FFI
+#[repr(i32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheStatus {
+    Ok = 0,
+    BadMagic = 1,
+    VersionMismatch = 2,
+    ConfigMismatch = 3,
+    SourceMismatch = 4,
+    Corrupt = 5,
+}
+
+#[derive(Debug)]
+pub enum CacheError {
+    BadMagic,
+    VersionMismatch { found: u16 },
+    ConfigMismatch,
+    SourceMismatch,
+    Corrupt(String),
+}
+
+impl CacheError {
+    pub fn status(&self) -> CacheStatus {
+        match self {
+            CacheError::BadMagic => CacheStatus::BadMagic,
+            CacheError::VersionMismatch { .. } => CacheStatus::VersionMismatch,
+            CacheError::ConfigMismatch => CacheStatus::ConfigMismatch,
+            CacheError::SourceMismatch => CacheStatus::SourceMismatch,
+            CacheError::Corrupt(_) => CacheStatus::Corrupt,
+        }
+    }
+}
+
+impl fmt::Display for CacheError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CacheError::BadMagic => write!(f, "Not a feature cache (missing header)."),
+            CacheError::VersionMismatch { found } => write!(
+                f,
+                "Feature cache version {} is not supported, expected {}.",
+                found, CACHE_VERSION
+            ),
+            CacheError::ConfigMismatch => {
+                write!(f, "Feature cache was analyzed with different parameters.")
+            }
+            CacheError::SourceMismatch => {
+                write!(f, "Feature cache does not belong to this audio file.")
+            }
+            CacheError::Corrupt(e) => write!(f, "Feature cache is corrupt: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CacheError {}
+
+// what the caller expects the cache to match, None skips the check
+#[derive(Default)]
+pub struct CacheExpectation<'a> {
+    pub config: Option<&'a AnalysisConfig>,
+    pub source_hash: Option<u64>,
+}
+
+pub fn encode_features(
+    features: &WorldFeatures,
+    source_hash: u64,
+    compression: Compression,
+) -> Result<Vec<u8>> {
+    let bincode_config = config::standard();
+    let header = CacheHeader {
+        version: CACHE_VERSION,
+        config: features.config.clone(),
+        source_hash,
+        compression,
+    };
+
+    let mut bytes = CACHE_MAGIC.to_vec();
+    bytes.extend(bincode::encode_to_vec(&header, bincode_config)?);
+
+    let payload = bincode::encode_to_vec(features, bincode_config)?;
+    match compression {
+        Compression::None => bytes.extend(payload),
+        Compression::Deflate => {
+            let mut encoder = DeflateEncoder::new(bytes, DeflateLevel::default());
+            encoder.write_all(&payload)?;
+            bytes = encoder.finish()?;
+        }
+    }
+    Ok(bytes)
+}
+
+pub fn read_header(bytes: &[u8]) -> Result<(CacheHeader, usize), CacheError> {
+    if bytes.len() < CACHE_MAGIC.len() || &bytes[..CACHE_MAGIC.len()] != CACHE_MAGIC {
+        return Err(CacheError::BadMagic);
+    }
+    let body = &bytes[CACHE_MAGIC.len()..];
+
+    // the version comes first so it can be checked before the rest of the header
+    let (version, _) = bincode::decode_from_slice::<u16, _>(body, config::standard())
+        .map_err(|e| CacheError::Corrupt(e.to_string()))?;
+    if version != CACHE_VERSION {
+        return Err(CacheError::VersionMismatch { found: version });
+    }
+
+    let (header, read) = bincode::decode_from_slice::<CacheHeader, _>(body, config::standard())
+        .map_err(|e| CacheError::Corrupt(e.to_string()))?;
+    Ok((header, CACHE_MAGIC.len() + read))
+}
+
+// straycat-rs writes WorldFeatures without the analysis config or any header
+#[derive(Decode)]
+struct StraycatFeatures {
+    base_f0: f64,
+    f0: Vec<f64>,
+    mgc: Vec<Vec<f64>>,
+    bap: Vec<Vec<f64>>,
+}
+
+// straycat-rs always analyzes with Harvest and the constants in consts.rs
+fn straycat_config() -> AnalysisConfig {
+    AnalysisConfig {
+        estimator: F0Estimator::Harvest,
+        ..Default::default()
+    }
+}
+
+// same as GetNumberOfAperiodicities in WORLD
+fn aperiodicity_dims(sample_rate: u32) -> usize {
+    ((sample_rate as f64 / 2. - 3000.).min(15000.) / 3000.) as usize
+}
+
+// data that doesn't decode at all isn't a straycat-rs file; once it does, wrong
+// dimensions are a config mismatch and anything else is corruption
+fn decode_straycat_features(bytes: &[u8]) -> Result<WorldFeatures, CacheError> {
+    let (features, read) =
+        bincode::decode_from_slice::<StraycatFeatures, _>(bytes, config::standard())
+            .map_err(|_| CacheError::BadMagic)?;
+    if read != bytes.len() {
+        return Err(CacheError::Corrupt(
+            "trailing bytes after the features".to_string(),
+        ));
+    }
+
+    let frames = features.f0.len();
+    let bap_dims = aperiodicity_dims(consts::SAMPLE_RATE);
+    if frames == 0 || features.mgc.len() != frames || features.bap.len() != frames {
+        return Err(CacheError::Corrupt(format!(
+            "frame counts differ: {} f0, {} mgc, {} bap",
+            frames,
+            features.mgc.len(),
+            features.bap.len()
+        )));
+    }
+    if features
+        .mgc
+        .iter()
+        .any(|frame| frame.len() != consts::MGC_DIMS as usize)
+        || features.bap.iter().any(|frame| frame.len() != bap_dims)
+    {
+        return Err(CacheError::ConfigMismatch);
+    }
+    let all_finite = std::iter::once(&features.base_f0)
+        .chain(&features.f0)
+        .chain(features.mgc.iter().flatten())
+        .chain(features.bap.iter().flatten())
+        .all(|v| v.is_finite());
+    if !all_finite {
+        return Err(CacheError::Corrupt("non-finite values".to_string()));
+    }
+
+    Ok(WorldFeatures {
+        config: straycat_config(),
+        base_f0: features.base_f0,
+        f0: features.f0,
+        mgc: features.mgc,
+        bap: features.bap,
+    })
+}
+
+// the wav a straycat-rs feature file belongs to: "a_wav.sc" or "a.sc" -> "a.wav"
+pub fn straycat_sample_path(path: &str) -> Option<String> {
+    let dot = path.rfind('.')?;
+    if !path[dot + 1..].eq_ignore_ascii_case(consts::FEATURE_EXT) {
+        return None;
+    }
+    let stem = &path[..dot];
+    let stem = match stem.len().checked_sub(4) {
+        Some(i) if stem.is_char_boundary(i) && stem[i..].eq_ignore_ascii_case("_wav") => &stem[..i],
+        _ => stem,
+    };
+    Some(format!("{}.wav", stem))
+}
+
+// headerless data is tried as a straycat-rs feature file; those carry no source
+// hash, so only the analysis config can be checked
+pub fn decode_features(
+    bytes: &[u8],
+    expectation: &CacheExpectation,
+) -> Result<WorldFeatures, CacheError> {
+    let (header, payload_start) = match read_header(bytes) {
+        Ok(header) => header,
+        Err(CacheError::BadMagic) => {
+            let features = decode_straycat_features(bytes)?;
+            // straycat-rs always estimates F0 itself, the frq setting doesn't apply
+            if let Some(config) = expectation.config
+                && (AnalysisConfig {
+                    use_frq: false,
+                    ..config.clone()
+                }) != features.config
+            {
+                return Err(CacheError::ConfigMismatch);
+            }
+            return Ok(features);
+        }
+        Err(e) => return Err(e),
+    };
+
+    if let Some(config) = expectation.config
+        && *config != header.config
+    {
+        return Err(CacheError::ConfigMismatch);
+    }
+    if let Some(source_hash) = expectation.source_hash
+        && source_hash != header.source_hash
+    {
+        return Err(CacheError::SourceMismatch);
+    }
+
+    let compressed = &bytes[payload_start..];
+    let payload = match header.compression {
+        Compression::None => compressed.to_vec(),
+        Compression::Deflate => {
+            let mut payload = Vec::new();
+            DeflateDecoder::new(compressed)
+                .read_to_end(&mut payload)
+                .map_err(|e| CacheError::Corrupt(e.to_string()))?;
+            payload
+        }
+    };
+
+    let (features, _) =
+        bincode::decode_from_slice::<WorldFeatures, _>(&payload, config::standard())
+            .map_err(|e| CacheError::Corrupt(e.to_string()))?;
+    if features.config != header.config {
+        return Err(CacheError::Corrupt(
+            "header and payload analysis parameters differ".to_string(),
+        ));
+    }
+    Ok(features)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        CACHE_MAGIC, CacheError, CacheExpectation, Compression, decode_features, encode_features,
+        straycat_sample_path,
+    };
+    use crate::consts;
+    use crate::world::features::{AnalysisConfig, WorldFeatures};
+
+    fn test_features() -> WorldFeatures {
+        WorldFeatures {
+            config: AnalysisConfig::default(),
+            base_f0: 220.,
+            f0: vec![0., 220., 221., 0.],
+            mgc: vec![vec![0.5; 4]; 4],
+            bap: vec![vec![-1.; 2]; 4],
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let features = test_features();
+        for compression in [Compression::None, Compression::Deflate] {
+            let bytes = encode_features(&features, 42, compression).unwrap();
+            let expectation = CacheExpectation {
+                config: Some(&features.config),
+                source_hash: Some(42),
+            };
+            let decoded = decode_features(&bytes, &expectation).unwrap();
+            assert_eq!(decoded.f0, features.f0);
+            assert_eq!(decoded.mgc, features.mgc);
+        }
+    }
+
+    #[test]
+    fn test_mismatch() {
+        let features = test_features();
+        let mut bytes = encode_features(&features, 42, Compression::Deflate).unwrap();
+
+        let expectation = CacheExpectation {
+            config: None,
+            source_hash: Some(7),
+        };
+        assert!(matches!(
+            decode_features(&bytes, &expectation),
+            Err(CacheError::SourceMismatch)
+        ));
+
+        let other = AnalysisConfig {
+            frame_period: 10.,
+            ..Default::default()
+        };
+        let expectation = CacheExpectation {
+            config: Some(&other),
+            source_hash: None,
+        };
+        assert!(matches!(
+            decode_features(&bytes, &expectation),
+            Err(CacheError::ConfigMismatch)
+        ));
+
+        bytes[CACHE_MAGIC.len()] = 99;
+        assert!(matches!(
+            decode_features(&bytes, &CacheExpectation::default()),
+            Err(CacheError::VersionMismatch { found: 99 })
+        ));
+        assert!(matches!(
+            decode_features(&bytes[4..], &CacheExpectation::default()),
+            Err(CacheError::BadMagic)
+        ));
+    }
+
+    #[test]
+    fn test_straycat_import() {
+        let frames = 3;
+        let mgc = vec![vec![0.1; consts::MGC_DIMS as usize]; frames];
+        let bap = vec![vec![-0.5; 5]; frames];
+        let payload = (220f64, vec![220f64; frames], mgc.clone(), bap.clone());
+        let bytes = bincode::encode_to_vec(&payload, bincode::config::standard()).unwrap();
+
+        let features = decode_features(&bytes, &CacheExpectation::default()).unwrap();
+        assert_eq!(features.config, AnalysisConfig::default());
+        assert_eq!(features.mgc, mgc);
+        assert_eq!(features.bap, bap);
+
+        let frq_config = AnalysisConfig {
+            use_frq: true,
+            ..Default::default()
+        };
+        let expectation = CacheExpectation {
+            config: Some(&frq_config),
+            source_hash: None,
+        };
+        assert!(decode_features(&bytes, &expectation).is_ok());
+
+        let short_mgc = (
+            220f64,
+            vec![220f64; frames],
+            vec![vec![0.1; 8]; frames],
+            bap,
+        );
+        let bytes = bincode::encode_to_vec(&short_mgc, bincode::config::standard()).unwrap();
+        assert!(matches!(
+            decode_features(&bytes, &CacheExpectation::default()),
+            Err(CacheError::ConfigMismatch)
+        ));
+
+        let nan_f0 = (
+            220f64,
+            vec![f64::NAN; frames],
+            mgc,
+            vec![vec![-0.5; 5]; frames],
+        );
+        let bytes = bincode::encode_to_vec(&nan_f0, bincode::config::standard()).unwrap();
+        assert!(matches!(
+            decode_features(&bytes, &CacheExpectation::default()),
+            Err(CacheError::Corrupt(_))
+        ));
+        assert!(matches!(
+            decode_features(b"not a feature file", &CacheExpectation::default()),
+            Err(CacheError::BadMagic)
+        ));
+
+        assert_eq!(
+            straycat_sample_path("強/あ_wav.sc").as_deref(),
+            Some("強/あ.wav")
+        );
+        assert_eq!(straycat_sample_path("ka.SC").as_deref(), Some("ka.wav"));
+        assert_eq!(straycat_sample_path("ka.wav"), None);
+    }
+}
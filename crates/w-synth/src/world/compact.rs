@@ -0,0 +1,209 @@
+use crate::world::features::{AnalysisConfig, WorldFeatures};
+use std::borrow::Cow;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FeatureStorage {
+    Full,
+    F32,
+    Q16,
+}
+
+impl FeatureStorage {
+    // mapping used by the FFI
+    pub fn from_code(code: i32) -> Option<Self> {
+        match code {
+            0 => Some(FeatureStorage::Full),
+            1 => Some(FeatureStorage::F32),
+            2 => Some(FeatureStorage::Q16),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Coefficients {
+    F32(Vec<f32>),
+    // per column (min, step), value = min + q * step
+    Q16 {
+        data: Vec<u16>,
+        ranges: Vec<(f32, f32)>,
+    },
+}
+
+// mgc and bap of all frames in one row-major array: [frame][mgc.., bap..]
+#[derive(Clone, Debug)]
+pub struct CompactFeatures {
+    config: AnalysisConfig,
+    base_f0: f64,
+    f0: Vec<f32>,
+    mgc_dims: usize,
+    bap_dims: usize,
+    coeffs: Coefficients,
+}
+
+impl CompactFeatures {
+    pub fn new(features: &WorldFeatures, quantize: bool) -> Self {
+        let mgc_dims = features.mgc.first().map_or(0, |f| f.len());
+        let bap_dims = features.bap.first().map_or(0, |f| f.len());
+        let row_len = mgc_dims + bap_dims;
+        let rows = features.mgc.iter().zip(features.bap.iter());
+
+        let coeffs = if quantize {
+            let mut ranges = vec![(f64::INFINITY, f64::NEG_INFINITY); row_len];
+            for (mgc, bap) in rows.clone() {
+                for (range, v) in ranges.iter_mut().zip(mgc.iter().chain(bap.iter())) {
+                    range.0 = range.0.min(*v);
+                    range.1 = range.1.max(*v);
+                }
+            }
+            let ranges: Vec<(f32, f32)> = ranges
+                .into_iter()
+                .map(|(min, max)| {
+                    let min = if min.is_finite() { min } else { 0. };
+                    let step = if max > min {
+                        (max - min) / u16::MAX as f64
+                    } else {
+                        0.
+                    };
+                    (min as f32, step as f32)
+                })
+                .collect();
+
+            let mut data = Vec::with_capacity(features.mgc.len() * row_len);
+            for (mgc, bap) in rows {
+                for ((min, step), v) in ranges.iter().zip(mgc.iter().chain(bap.iter())) {
+                    let q = if *step > 0. {
+                        ((v - *min as f64) / *step as f64).round()
+                    } else {
+                        0.
+                    };
+                    data.push(q.clamp(0., u16::MAX as f64) as u16);
+                }
+            }
+            Coefficients::Q16 { data, ranges }
+        } else {
+            let mut data = Vec::with_capacity(features.mgc.len() * row_len);
+            for (mgc, bap) in rows {
+                data.extend(mgc.iter().chain(bap.iter()).map(|v| *v as f32));
+            }
+            Coefficients::F32(data)
+        };
+
+        Self {
+            config: features.config.clone(),
+            base_f0: features.base_f0,
+            f0: features.f0.iter().map(|f| *f as f32).collect(),
+            mgc_dims,
+            bap_dims,
+            coeffs,
+        }
+    }
+
+    fn row(&self, frame: usize) -> Vec<f64> {
+        let row_len = self.mgc_dims + self.bap_dims;
+        let range = frame * row_len..(frame + 1) * row_len;
+        match &self.coeffs {
+            Coefficients::F32(data) => data[range].iter().map(|v| *v as f64).collect(),
+            Coefficients::Q16 { data, ranges } => data[range]
+                .iter()
+                .zip(ranges.iter())
+                .map(|(q, (min, step))| *min as f64 + *q as f64 * *step as f64)
+                .collect(),
+        }
+    }
+
+    pub fn length_ms(&self) -> f64 {
+        self.f0.len() as f64 * self.config.frame_period
+    }
+
+    pub fn decode(&self) -> WorldFeatures {
+        let frames = self.f0.len();
+        let mut mgc = Vec::with_capacity(frames);
+        let mut bap = Vec::with_capacity(frames);
+        for frame in 0..frames {
+            let mut row = self.row(frame);
+            bap.push(row.split_off(self.mgc_dims));
+            mgc.push(row);
+        }
+
+        WorldFeatures {
+            config: self.config.clone(),
+            base_f0: self.base_f0,
+            f0: self.f0.iter().map(|f| *f as f64).collect(),
+            mgc,
+            bap,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum CachedFeatures {
+    Full(WorldFeatures),
+    Compact(CompactFeatures),
+}
+
+impl CachedFeatures {
+    pub fn new(features: WorldFeatures, storage: FeatureStorage) -> Self {
+        match storage {
+            FeatureStorage::Full => CachedFeatures::Full(features),
+            FeatureStorage::F32 => CachedFeatures::Compact(CompactFeatures::new(&features, false)),
+            FeatureStorage::Q16 => CachedFeatures::Compact(CompactFeatures::new(&features, true)),
+        }
+    }
+
+    pub fn length_ms(&self) -> f64 {
+        match self {
+            CachedFeatures::Full(features) => {
+                features.f0.len() as f64 * features.config.frame_period
+            }
+            CachedFeatures::Compact(compact) => compact.length_ms(),
+        }
+    }
+
+    // compact entries are expanded on demand, once per rendered note
+    pub fn features(&self) -> Cow<'_, WorldFeatures> {
+        match self {
+            CachedFeatures::Full(features) => Cow::Borrowed(features),
+            CachedFeatures::Compact(compact) => Cow::Owned(compact.decode()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CachedFeatures, FeatureStorage};
+    use crate::world::features::{AnalysisConfig, WorldFeatures};
+
+    #[test]
+    fn test_compact_roundtrip() {
+        let features = WorldFeatures {
+            config: AnalysisConfig::default(),
+            base_f0: 220.,
+            f0: vec![0., 220.5, 221.25, 0.],
+            mgc: (0..4).map(|i| vec![i as f64 * -1.5, 0.25, 3.]).collect(),
+            bap: (0..4).map(|i| vec![-60. + i as f64, -0.5]).collect(),
+        };
+
+        for (storage, tolerance) in [(FeatureStorage::F32, 1e-6), (FeatureStorage::Q16, 1e-3)] {
+            let cached = CachedFeatures::new(features.clone(), storage);
+            let decoded = cached.features();
+            assert_eq!(decoded.f0, features.f0);
+            for (a, b) in decoded
+                .mgc
+                .iter()
+                .flatten()
+                .zip(features.mgc.iter().flatten())
+            {
+                assert!((a - b).abs() < tolerance);
+            }
+            for (a, b) in decoded
+                .bap
+                .iter()
+                .flatten()
+                .zip(features.bap.iter().flatten())
+            {
+                assert!((a - b).abs() < tolerance);
+            }
+        }
+    }
+}
@@ -1,26 +1,107 @@
 use crate::consts;
+use crate::frq::FrqData;
+use crate::world::WORLD_LOCK;
 use anyhow::{Result, anyhow};
 use bincode::Decode;
 use bincode::Encode;
-use rsworld::{cheaptrick, code_aperiodicity, code_spectral_envelope, d4c, harvest};
-use rsworld_sys::{CheapTrickOption, D4COption, HarvestOption};
+use rsworld::{
+    cheaptrick, code_aperiodicity_with_fft_size, code_spectral_envelope, d4c_with_fft_size, dio,
+    harvest, stonemask,
+};
+use rsworld_sys::{CheapTrickOption, D4COption, DioOption, HarvestOption};
 use serde::{Deserialize, Serialize};
 
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Decode, Encode)]
+#[serde(rename_all = "lowercase")]
+pub enum F0Estimator {
+    // slow, robust
+    Harvest,
+    // fast, refined with StoneMask
+    Dio,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Decode, Encode)]
+#[serde(default)]
+pub struct AnalysisConfig {
+    pub estimator: F0Estimator,
+    pub f0_floor: f64,
+    pub f0_ceil: f64,
+    pub frame_period: f64,
+    pub fft_size: i32,
+    pub mgc_dims: i32,
+    pub d4c_threshold: f64,
+    // take F0 from the sample's .frq file when one is given instead of estimating it
+    pub use_frq: bool,
+}
+
+impl Default for AnalysisConfig {
+    fn default() -> Self {
+        Self {
+            estimator: F0Estimator::Harvest,
+            f0_floor: consts::F0_FLOOR,
+            f0_ceil: consts::F0_CEIL,
+            frame_period: consts::FRAME_PERIOD,
+            fft_size: consts::FFT_SIZE,
+            mgc_dims: consts::MGC_DIMS,
+            d4c_threshold: consts::D4C_THRESHOLD,
+            use_frq: false,
+        }
+    }
+}
+
+impl AnalysisConfig {
+    // lowest F0 CheapTrick can resolve with this FFT size, same as GetF0FloorForCheapTrick
+    pub fn cheaptrick_f0_floor(&self) -> f64 {
+        3. * consts::SAMPLE_RATE as f64 / (self.fft_size - 3) as f64
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if !self.f0_floor.is_finite() || self.f0_floor <= 0. || self.f0_ceil <= self.f0_floor {
+            return Err(anyhow!(
+                "Invalid F0 range: floor {} Hz, ceil {} Hz.",
+                self.f0_floor,
+                self.f0_ceil
+            ));
+        }
+        if !self.frame_period.is_finite() || self.frame_period <= 0. {
+            return Err(anyhow!("Invalid frame period: {} ms.", self.frame_period));
+        }
+        if self.fft_size < 128 || self.fft_size.count_ones() != 1 {
+            return Err(anyhow!(
+                "Invalid FFT size: {}. A power of two of at least 128 is required.",
+                self.fft_size
+            ));
+        }
+        if self.mgc_dims <= 0 || self.mgc_dims > self.fft_size / 2 + 1 {
+            return Err(anyhow!(
+                "Invalid MGC dimensions: {} for FFT size {}.",
+                self.mgc_dims,
+                self.fft_size
+            ));
+        }
+        if !(0. ..=1.).contains(&self.d4c_threshold) {
+            return Err(anyhow!("Invalid D4C threshold: {}.", self.d4c_threshold));
+        }
+        Ok(())
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, Decode, Encode)]
 pub struct WorldFeatures {
+    pub config: AnalysisConfig,
     pub base_f0: f64,
     pub f0: Vec<f64>,
     pub mgc: Vec<Vec<f64>>,
     pub bap: Vec<Vec<f64>>,
 }
 
-fn calculate_base_f0(f0: &Vec<f64>) -> f64 {
+fn calculate_base_f0(f0: &Vec<f64>, f0_floor: f64, f0_ceil: f64) -> f64 {
     let n = f0.len();
     let mut base_f0 = 0.;
     let mut tally = 0.;
 
     for i in 0..n {
-        if f0[i] >= consts::F0_FLOOR && f0[i] <= consts::F0_CEIL {
+        if f0[i] >= f0_floor && f0[i] <= f0_ceil {
             let q = if i == 0 {
                 f0[1] - f0[0]
             } else if i == n - 1 {
@@ -41,39 +122,101 @@ fn calculate_base_f0(f0: &Vec<f64>) -> f64 {
     base_f0
 }
 
-pub fn generate_features(audio: Vec<f64>, threshold: Option<f64>) -> Result<WorldFeatures> {
-    if audio.len() < consts::FFT_SIZE as usize {
+// same frame count Harvest and Dio produce
+fn frame_count(samples: usize, frame_period: f64) -> usize {
+    (samples as f64 * 1000. / consts::SAMPLE_RATE as f64 / frame_period) as usize + 1
+}
+
+fn estimate_f0(
+    audio: &Vec<f64>,
+    config: &AnalysisConfig,
+    frq: Option<&FrqData>,
+) -> (Vec<f64>, Vec<f64>) {
+    if config.use_frq
+        && let Some(frq) = frq
+    {
+        let frames = frame_count(audio.len(), config.frame_period);
+        let t = (0..frames)
+            .map(|i| i as f64 * config.frame_period / 1000.)
+            .collect();
+        let f0 = frq.resample(
+            consts::SAMPLE_RATE,
+            config.frame_period,
+            frames,
+            config.f0_floor,
+            config.f0_ceil,
+        );
+        return (t, f0);
+    }
+    match config.estimator {
+        F0Estimator::Harvest => {
+            let harvest_opts = HarvestOption {
+                f0_floor: config.f0_floor,
+                f0_ceil: config.f0_ceil,
+                frame_period: config.frame_period,
+            };
+            harvest(audio, consts::SAMPLE_RATE as i32, &harvest_opts)
+        }
+        F0Estimator::Dio => {
+            let mut dio_opts = DioOption::new();
+            dio_opts.f0_floor = config.f0_floor;
+            dio_opts.f0_ceil = config.f0_ceil;
+            dio_opts.frame_period = config.frame_period;
+
+            let (t, f0) = dio(audio, consts::SAMPLE_RATE as i32, &dio_opts);
+            let f0 = stonemask(audio, consts::SAMPLE_RATE as i32, &t, &f0);
+            (t, f0)
+        }
+    }
+}
+
+// frq replaces the estimated F0 when config.use_frq is set; the features record
+// use_frq only when a frq track was actually used
+pub fn generate_features(
+    audio: Vec<f64>,
+    config: &AnalysisConfig,
+    frq: Option<&FrqData>,
+) -> Result<WorldFeatures> {
+    config.validate()?;
+    if audio.len() < config.fft_size as usize {
         return Err(anyhow!(
             "Audio signal is too short for analysis. At least {} samples are required, found {}.",
-            consts::FFT_SIZE,
+            config.fft_size,
             audio.len()
         ));
     }
-    let harvest_opts = HarvestOption {
-        f0_floor: consts::F0_FLOOR,
-        f0_ceil: consts::F0_CEIL,
-        frame_period: consts::FRAME_PERIOD,
-    };
-
+    // CheapTrick derives its FFT size from the floor, so pass the floor matching fft_size
     let mut cheaptrick_opts = CheapTrickOption {
         q1: consts::SPEC_Q1,
-        f0_floor: consts::F0_FLOOR,
-        fft_size: consts::FFT_SIZE,
+        f0_floor: config.cheaptrick_f0_floor(),
+        fft_size: config.fft_size,
     };
 
     let d4c_opts = D4COption {
-        threshold: threshold.unwrap_or(consts::D4C_THRESHOLD),
+        threshold: config.d4c_threshold,
     };
 
-    let (t, f0) = harvest(&audio, consts::SAMPLE_RATE as i32, &harvest_opts);
-    let sp = cheaptrick(
-        &audio,
-        consts::SAMPLE_RATE as i32,
-        &t,
-        &f0,
-        &mut cheaptrick_opts,
-    );
-    let mut ap = d4c(&audio, consts::SAMPLE_RATE as i32, &t, &f0, &d4c_opts);
+    // F0 estimation doesn't touch randn and stays parallel; CheapTrick and D4C do
+    let (t, f0) = estimate_f0(&audio, config, frq);
+    let (sp, mut ap) = {
+        let _guard = WORLD_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let sp = cheaptrick(
+            &audio,
+            consts::SAMPLE_RATE as i32,
+            &t,
+            &f0,
+            &mut cheaptrick_opts,
+        );
+        let ap = d4c_with_fft_size(
+            &audio,
+            consts::SAMPLE_RATE as i32,
+            &t,
+            &f0,
+            config.fft_size,
+            &d4c_opts,
+        );
+        (sp, ap)
+    };
 
     ap.iter_mut().for_each(|ap_frame| {
         ap_frame.iter_mut().for_each(|a| {
@@ -83,17 +226,26 @@ pub fn generate_features(audio: Vec<f64>, threshold: Option<f64>) -> Result<Worl
         })
     });
 
-    let base_f0 = calculate_base_f0(&f0);
+    let base_f0 = calculate_base_f0(&f0, config.f0_floor, config.f0_ceil);
     let mgc = code_spectral_envelope(
         &sp,
         f0.len() as i32,
         consts::SAMPLE_RATE as i32,
-        consts::FFT_SIZE,
-        consts::MGC_DIMS,
+        config.fft_size,
+        config.mgc_dims,
+    );
+    let bap = code_aperiodicity_with_fft_size(
+        &ap,
+        f0.len() as i32,
+        consts::SAMPLE_RATE as i32,
+        config.fft_size,
     );
-    let bap = code_aperiodicity(&ap, f0.len() as i32, consts::SAMPLE_RATE as i32);
 
     let features = WorldFeatures {
+        config: AnalysisConfig {
+            use_frq: config.use_frq && frq.is_some(),
+            ..config.clone()
+        },
         base_f0,
         f0,
         mgc,
@@ -1,2 +1,10 @@
+pub mod cache;
+pub mod compact;
 pub mod features;
 pub mod synthesis;
+
+use std::sync::Mutex;
+
+// WORLD's randn keeps its state in C globals used by CheapTrick, D4C and Synthesis,
+// so only those calls must not overlap; serialized they stay deterministic
+pub static WORLD_LOCK: Mutex<()> = Mutex::new(());
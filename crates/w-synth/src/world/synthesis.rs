@@ -1,23 +1,29 @@
 use crate::consts;
+use crate::world::WORLD_LOCK;
 use rsworld::synthesis;
 
-pub fn synthesize(f0: &Vec<f64>, sp: &mut Vec<Vec<f64>>, ap: &mut Vec<Vec<f64>>) -> Vec<f64> {
+pub fn synthesize(
+    f0: &Vec<f64>,
+    sp: &mut Vec<Vec<f64>>,
+    ap: &mut Vec<Vec<f64>>,
+    frame_period: f64,
+) -> Vec<f64> {
     sp.iter_mut()
         .for_each(|sp_frame| sp_frame.iter_mut().for_each(|s| *s = s.max(1e-16)));
 
     ap.iter_mut()
         .for_each(|ap_frame| ap_frame.iter_mut().for_each(|a| *a = a.clamp(0., 1.)));
 
-    synthesis(
-        &f0,
-        &sp,
-        &ap,
-        consts::FRAME_PERIOD,
-        consts::SAMPLE_RATE as i32,
-    )
+    let _guard = WORLD_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    synthesis(&f0, &sp, &ap, frame_period, consts::SAMPLE_RATE as i32)
 }
 
-pub fn synthesize_harmonic(f0: &Vec<f64>, sp: &Vec<Vec<f64>>, ap: &Vec<Vec<f64>>) -> Vec<f64> {
+pub fn synthesize_harmonic(
+    f0: &Vec<f64>,
+    sp: &Vec<Vec<f64>>,
+    ap: &Vec<Vec<f64>>,
+    frame_period: f64,
+) -> Vec<f64> {
     let mut sp_harmonic: Vec<Vec<f64>> = sp
         .iter()
         .zip(ap.iter())
@@ -33,7 +39,7 @@ pub fn synthesize_harmonic(f0: &Vec<f64>, sp: &Vec<Vec<f64>>, ap: &Vec<Vec<f64>>
         .iter()
         .map(|frame| frame.iter().map(|_| 0.).collect())
         .collect();
-    synthesize(f0, &mut sp_harmonic, &mut ap_harmonic)
+    synthesize(f0, &mut sp_harmonic, &mut ap_harmonic, frame_period)
 }
 
 pub fn synthesize_aperiodic(
@@ -41,6 +47,7 @@ pub fn synthesize_aperiodic(
     sp: &mut Vec<Vec<f64>>,
     ap: &Vec<Vec<f64>>,
     correct_sp: bool,
+    frame_period: f64,
 ) -> Vec<f64> {
     let mut ap_aperiodic: Vec<Vec<f64>> = ap
         .iter()
@@ -59,8 +66,8 @@ pub fn synthesize_aperiodic(
             })
             .collect();
 
-        synthesize(f0, &mut sp_aperiodic, &mut ap_aperiodic)
+        synthesize(f0, &mut sp_aperiodic, &mut ap_aperiodic, frame_period)
     } else {
-        synthesize(f0, sp, &mut ap_aperiodic)
+        synthesize(f0, sp, &mut ap_aperiodic, frame_period)
     }
 }
@@ -1,6 +1,6 @@
 import { writable, get, derived } from 'svelte/store';
 import { browser } from '$app/environment';
-import type { AppState, Note, VoicebankInfo, PitchbendPoint, ProjectInfo, NoteInfo, EditorMode, SettingsState } from './types';
+import type { AppState, Note, VoicebankInfo, PitchbendPoint, ProjectInfo, NoteInfo, EditorMode, SettingsState, BeatCurvePoint, ExpressionKind, OtoIssue, VoicebankMetadata, VoicebankMetadataFiles, ArchiveSummary } from './types';
 import 'uuid';
 
 function decodeWav(wavBytes: Uint8Array): { pcmData: Float32Array; sampleRate: number } {
@@ -74,6 +74,10 @@ function encodeWav(pcmData: Float32Array, sampleRate: number): Uint8Array {
 const initialSettings: SettingsState = {
     project: {
 		analysisCoreCount: 0, 
+		useFrq: false,
+		autoPortamento: true,
+		portamentoLength: 80,
+		portamentoStart: -40,
 	},
 	pianoRoll: {
 		defaultLyric: 'あ',
@@ -96,6 +100,8 @@ const initialAppState: AppState = {
 	playback: { isPlaying: false, currentTime: 0, masterAudioBuffer: null },
 	synthesis: { isSynthesizing: false, progress: 0, statusMessage: '' },
 	tempo: 120,
+	pitchCurve: [],
+	expressions: {},
 	gridDivision: 8,
 	editorMode: 'select',
 	isNoteEditorOpen: false,
@@ -118,6 +124,10 @@ export const pianorollViewSettings = writable({
 });
 
 let synthesisWorker: Worker | null = null;
+// metadata the worker parsed for the voicebank being loaded
+let pendingVoicebankMetadata: VoicebankMetadata | null = null;
+let pendingFeatureImport: ((imported: string[]) => void) | null = null;
+let pendingZipLoad: { resolve: (result: { summary: ArchiveSummary; info: VoicebankMetadata | null }) => void; reject: (error: Error) => void } | null = null;
 
 export const setStatus = (message: string) => {
     console.log(message)
@@ -156,8 +166,29 @@ export const initializeClient = () => {
                     playCurrentBuffer();
                 }
                 break;
+            case 'voicebank_info':
+                pendingVoicebankMetadata = payload.info;
+                appState.update(s => s.currentVoicebank
+                    ? { ...s, currentVoicebank: { ...s.currentVoicebank, metadata: payload.info } }
+                    : s);
+                break;
+            case 'features_imported':
+                pendingFeatureImport?.(payload.imported);
+                pendingFeatureImport = null;
+                break;
+            case 'oto_validated':
+                for (const issue of payload.issues as (OtoIssue & { dir: string })[]) {
+                    console.warn(`oto.ini in '${issue.dir}', line ${issue.line}: ${issue.message}`);
+                }
+                break;
+            case 'zip_loaded':
+                pendingZipLoad?.resolve(payload);
+                pendingZipLoad = null;
+                break;
             case 'error':
                 const errorMessage = payload.message;
+                pendingZipLoad?.reject(new Error(errorMessage));
+                pendingZipLoad = null;
                 console.error("Worker error:", errorMessage);
                 setStatus(`Error: ${errorMessage}`);
                 appState.update(s => ({ ...s, synthesis: { isSynthesizing: false, progress: 0, statusMessage: `Error: ${errorMessage}` } }));
@@ -175,9 +206,12 @@ export const initializeClient = () => {
 
 export const loadVoicebank = async (
     voicebankInfo: VoicebankInfo,
-    otoData: Uint8Array,
+    otoFiles: { dir: string; data: Uint8Array }[],
     wavFiles: { name: string; data: Uint8Array }[],
-    prefixMapData: Uint8Array | null
+    prefixMapData: Uint8Array | null,
+    metadataFiles: VoicebankMetadataFiles = {},
+    featureFiles: { name: string; data: Uint8Array }[] = [],
+    frqFiles: { name: string; data: Uint8Array }[] = []
 ) => {
     if (!synthesisWorker) {
         setStatus('Error: Engine was not initialized.');
@@ -192,12 +226,33 @@ export const loadVoicebank = async (
     appState.update(state => ({ ...state, currentVoicebank: null, playback: initialAppState.playback }));
 
     try {
-        synthesisWorker.postMessage({ type: 'load_oto', payload: { data: otoData } }, [otoData.buffer]);
+        // the originals are transferred to the worker; these are checked once analysis is done
+        const otoSources = otoFiles.map(f => ({ dir: f.dir, data: f.data.slice() }));
+        synthesisWorker.postMessage(
+            { type: 'load_oto', payload: { files: otoFiles } },
+            otoFiles.map(f => f.data.buffer)
+        );
         
         if (prefixMapData) {
             synthesisWorker.postMessage({ type: 'load_prefix_map', payload: { data: prefixMapData } }, [prefixMapData.buffer]);
         }
 
+        if (Object.values(metadataFiles).some((data) => data)) {
+            pendingVoicebankMetadata = null;
+            synthesisWorker.postMessage({ type: 'load_voicebank_info', payload: { files: metadataFiles } });
+        }
+
+        if (featureFiles.length > 0) {
+            // straycat-rs features the engine accepts replace analyzing their wav
+            setStatus(`Importing ${featureFiles.length} straycat feature files...`);
+            const imported = await new Promise<string[]>((resolve) => {
+                pendingFeatureImport = resolve;
+                synthesisWorker!.postMessage({ type: 'import_features', payload: { files: featureFiles } });
+            });
+            const importedNames = new Set(imported);
+            wavFiles = wavFiles.filter((file) => !importedNames.has(file.name));
+        }
+
         setStatus('oto.ini loaded. Starting parallel analysis of WAV files...');
 
         const analysisPromise = new Promise<void>((resolve, reject) => {
@@ -212,8 +267,22 @@ export const loadVoicebank = async (
             }
             const workers: Worker[] = [];
             let filesToProcess = [...wavFiles];
+            // .frq files only matter when the setting is on, the rest is analyzed as usual
+            const analysisConfig = currentSettings.project.useFrq ? { use_frq: true } : undefined;
+            const frqByName = new Map(analysisConfig ? frqFiles.map((f) => [f.name, f.data]) : []);
+            const analyzerMessage = (file: { name: string; data: Uint8Array }) => {
+                const frqData = frqByName.get(file.name);
+                return {
+                    message: { filename: file.name, wavData: file.data, frqData, config: analysisConfig },
+                    transfer: frqData ? [file.data.buffer, frqData.buffer] : [file.data.buffer]
+                };
+            };
             let processedCount = 0;
             const totalFiles = wavFiles.length;
+            if (totalFiles === 0) {
+                resolve();
+                return;
+            }
 
             const onWorkerMessage = async (event: MessageEvent) => {
                 const { status, filename, featuresData, error } = event.data;
@@ -234,8 +303,8 @@ export const loadVoicebank = async (
                     workers.forEach(w => w.terminate());
                     resolve();
                 } else if (filesToProcess.length > 0) {
-                    const nextFile = filesToProcess.pop()!;
-                    (event.target as Worker).postMessage({ filename: nextFile.name, wavData: nextFile.data }, [nextFile.data.buffer]);
+                    const { message, transfer } = analyzerMessage(filesToProcess.pop()!);
+                    (event.target as Worker).postMessage(message, transfer);
                 }
             };
             
@@ -245,17 +314,22 @@ export const loadVoicebank = async (
                 worker.onerror = (e) => reject(new Error("Worker failed catastrophically."));
                 workers.push(worker);
                 if (filesToProcess.length > 0) {
-                    const file = filesToProcess.pop()!;
-                    worker.postMessage({ filename: file.name, wavData: file.data }, [file.data.buffer]);
+                    const { message, transfer } = analyzerMessage(filesToProcess.pop()!);
+                    worker.postMessage(message, transfer);
                 }
             }
         });
 
         await analysisPromise;
+        // sample lengths are known now, so oto problems such as bad cutoffs are reported once
+        synthesisWorker.postMessage(
+            { type: 'validate_oto', payload: { files: otoSources } },
+            otoSources.map(f => f.data.buffer)
+        );
         
         appState.update(state => ({
             ...state,
-            currentVoicebank: voicebankInfo,
+            currentVoicebank: { ...voicebankInfo, metadata: pendingVoicebankMetadata ?? undefined },
             status: `Voicebank "${voicebankInfo.name}" loaded successfully!`,
         }));
 
@@ -266,6 +340,56 @@ export const loadVoicebank = async (
     }
 };
 
+// the engine unpacks the archive itself, so CP932 file names match the oto.ini entries
+export const loadVoicebankZip = async (
+    voicebankInfo: VoicebankInfo,
+    zipData: Uint8Array,
+    readImage?: (imageName: string) => Promise<Blob | null>
+) => {
+    if (!synthesisWorker) {
+        setStatus('Error: Engine was not initialized.');
+        return null;
+    }
+
+    setStatus(`Loading voicebank "${voicebankInfo.name}"...`);
+    const oldVoicebank = get(appState).currentVoicebank;
+    if (oldVoicebank?.image) {
+        URL.revokeObjectURL(oldVoicebank.image);
+    }
+    appState.update(state => ({ ...state, currentVoicebank: null, playback: initialAppState.playback }));
+
+    try {
+        const loaded = new Promise<{ summary: ArchiveSummary; info: VoicebankMetadata | null }>((resolve, reject) => {
+            pendingZipLoad = { resolve, reject };
+        });
+        const { analysisCoreCount, useFrq } = get(settings).project;
+        setStatus('Unpacking and analyzing the voicebank...');
+        synthesisWorker.postMessage(
+            { type: 'load_zip', payload: { data: zipData, threads: analysisCoreCount, config: useFrq ? { use_frq: true } : null } },
+            [zipData.buffer]
+        );
+        const { summary, info } = await loaded;
+
+        const name = info?.name ?? voicebankInfo.name;
+        let image = voicebankInfo.image;
+        if (info?.image && readImage) {
+            const imageBlob = await readImage(info.image);
+            if (imageBlob) image = URL.createObjectURL(imageBlob);
+        }
+        appState.update(state => ({
+            ...state,
+            currentVoicebank: { ...voicebankInfo, name, image, metadata: info ?? undefined },
+            status: `Voicebank "${name}" loaded successfully! (${summary.analyzed} / ${summary.samples} samples)`,
+        }));
+        return info;
+    } catch (error) {
+        console.error('Error loading voicebank:', error);
+        setStatus(`Error: ${(error as Error).message}`);
+        appState.update(state => ({ ...state, currentVoicebank: null }));
+        return null;
+    }
+};
+
 function stopPlayback() {
     appState.update(s => ({ ...s, playback: { ...s.playback, isPlaying: false } }));
     if (masterAudioSource) {
@@ -310,11 +434,22 @@ export const startSynthesis = async () => {
         velocity: note.velocity ?? 100,
         volume: note.volume ?? 100,
         modulation: note.modulation ?? 0,
+        voice_color: note.voiceColor,
+        vibrato: note.vibrato,
     }));
 
+    const { autoPortamento, portamentoLength, portamentoStart } = get(settings).project;
     const projectData: ProjectInfo = {
         notes: noteInfos,
         tempo: state.tempo,
+        portamento: autoPortamento ? { length: portamentoLength, start: portamentoStart } : null,
+        pitch_curve: state.pitchCurve.map(p => ({ time: p.beat * msPerBeat, value: p.value })),
+        expressions: Object.fromEntries(
+            Object.entries(state.expressions).map(([kind, points]) => [
+                kind,
+                (points ?? []).map(p => ({ time: p.beat * msPerBeat, value: p.value })),
+            ])
+        ),
     };
 
     synthesisWorker.postMessage({ type: 'synthesize', payload: { projectData } });
@@ -578,6 +713,17 @@ export const deletePitchbendPoint = (noteId: string, pointId: string) => {
     }));
 };
 
+export const setPitchCurve = (pitchCurve: BeatCurvePoint[]) => {
+    appState.update(state => ({ ...state, pitchCurve: [...pitchCurve].sort((a, b) => a.beat - b.beat) }));
+};
+
+export const setExpressionCurve = (kind: ExpressionKind, points: BeatCurvePoint[]) => {
+    appState.update(state => ({
+        ...state,
+        expressions: { ...state.expressions, [kind]: [...points].sort((a, b) => a.beat - b.beat) },
+    }));
+};
+
 export const setTempo = (tempo: number) => {
     appState.update(state => ({ ...state, tempo }));
 };
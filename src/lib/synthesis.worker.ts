@@ -1,5 +1,5 @@
 import { WSynthEngineClient } from './wsynth-client.svelte';
-import type { ProjectInfo } from './types';
+import type { NoteInfo, ProjectInfo } from './types';
 
 let client: WSynthEngineClient | null = null;
 let enginePtr: number | null = null;
@@ -20,20 +20,117 @@ self.onmessage = async (e: MessageEvent) => {
 
             case 'load_oto':
                 if (!client || !enginePtr) throw new Error("Engine is not initialized in worker.");
-                const otoSuccess = await client.loadOto(enginePtr, payload.data);
+                client.clearVoicebank(enginePtr);
+                let otoSuccess = false;
+                for (const file of payload.files as { dir: string; data: Uint8Array }[]) {
+                    otoSuccess = (await client.addOto(enginePtr, file.dir, file.data)) || otoSuccess;
+                }
                 if (!otoSuccess) throw new Error("Failed to load OTO in worker.");
                 break;
             
+            case 'validate_oto':
+                if (!client || !enginePtr) throw new Error("Engine is not initialized in worker.");
+                const issues = [];
+                for (const file of payload.files as { dir: string; data: Uint8Array }[]) {
+                    const fileIssues = await client.validateOto(enginePtr, file.dir, file.data);
+                    if (!fileIssues) throw new Error(`Failed to validate oto.ini in '${file.dir}'.`);
+                    issues.push(...fileIssues.map((issue) => ({ ...issue, dir: file.dir })));
+                }
+                self.postMessage({ type: 'oto_validated', payload: { issues } });
+                break;
+
+            case 'update_oto':
+                if (!client || !enginePtr) throw new Error("Engine is not initialized in worker.");
+                if (!client.updateOto(enginePtr, payload.entry)) {
+                    throw new Error(`Failed to update oto entry '${payload.entry.alias}'.`);
+                }
+                break;
+
+            case 'write_oto':
+                if (!client || !enginePtr) throw new Error("Engine is not initialized in worker.");
+                const otoBytes = client.writeOto(enginePtr, payload.dir);
+                if (!otoBytes) throw new Error(`Failed to write oto.ini for '${payload.dir}'.`);
+                self.postMessage({ type: 'oto_written', payload: { dir: payload.dir, otoBytes } }, [otoBytes.buffer]);
+                break;
+
+            case 'auto_oto':
+                if (!client || !enginePtr) throw new Error("Engine is not initialized in worker.");
+                const drafts = client.autoOto(enginePtr, payload.style);
+                if (!drafts) throw new Error("Failed to estimate oto entries in worker.");
+                self.postMessage({ type: 'oto_drafted', payload: { entries: drafts } });
+                break;
+
             case 'load_prefix_map':
                 if (!client || !enginePtr || !payload.data) break;
                 await client.loadPrefixMap(enginePtr, payload.data);
                 break;
 
+            case 'load_voicebank_info':
+                if (!client || !enginePtr) throw new Error("Engine is not initialized in worker.");
+                const info = client.loadVoicebankInfo(enginePtr, payload.files);
+                if (!info) throw new Error("Failed to read voicebank metadata in worker.");
+                self.postMessage({ type: 'voicebank_info', payload: { info } });
+                break;
+
+            case 'load_zip':
+                if (!client || !enginePtr) throw new Error("Engine is not initialized in worker.");
+                const summary = client.loadZip(enginePtr, payload.data, payload.config ?? null, payload.threads ?? 0);
+                if (!summary) throw new Error("Failed to load the voicebank archive in worker.");
+                self.postMessage({ type: 'zip_loaded', payload: { summary, info: client.voicebankInfo(enginePtr) } });
+                break;
+
+            case 'add_voice_color':
+                if (!client || !enginePtr) throw new Error("Engine is not initialized in worker.");
+                if (!(await client.addVoiceColor(enginePtr, payload.name, payload.dir ?? null, payload.data ?? null))) {
+                    throw new Error(`Failed to add voice color '${payload.name}'.`);
+                }
+                break;
+
+            case 'load_pack':
+                if (!client || !enginePtr) throw new Error("Engine is not initialized in worker.");
+                const packSuccess = await client.loadPack(enginePtr, payload.data);
+                if (!packSuccess) throw new Error("Failed to load feature pack in worker.");
+                break;
+
+            case 'save_pack':
+                if (!client || !enginePtr) throw new Error("Engine is not initialized in worker.");
+                const packBytes = await client.savePack(enginePtr);
+                if (!packBytes) throw new Error("Failed to save feature pack in worker.");
+                self.postMessage({ type: 'pack_saved', payload: { packBytes } }, [packBytes.buffer]);
+                break;
+
+            case 'set_feature_storage':
+                if (!client || !enginePtr) throw new Error("Engine is not initialized in worker.");
+                if (!client.setFeatureStorage(enginePtr, payload.storage)) {
+                    throw new Error("Failed to set feature storage in worker.");
+                }
+                break;
+
             case 'cache_features':
                 if (!client || !enginePtr) throw new Error("Engine is not initialized in worker.");
                 await client.cacheFeatures(enginePtr, payload.filename, payload.featuresData);
                 break;
             
+            case 'import_features':
+                if (!client || !enginePtr) throw new Error("Engine is not initialized in worker.");
+                const imported: string[] = [];
+                for (const file of payload.files as { name: string; data: Uint8Array }[]) {
+                    if (await client.cacheFeatures(enginePtr, file.name, file.data)) imported.push(file.name);
+                }
+                self.postMessage({ type: 'features_imported', payload: { imported } });
+                break;
+
+            case 'kana_table':
+                if (!client) throw new Error("Engine is not initialized in worker.");
+                self.postMessage({ type: 'kana_table', payload: { table: client.kanaTable() } });
+                break;
+
+            case 'resolve_aliases':
+                if (!client || !enginePtr) throw new Error("Engine is not initialized in worker.");
+                const resolved = (payload.notes as NoteInfo[]).map((note) => client!.resolveAlias(enginePtr!, note));
+                self.postMessage({ type: 'aliases_resolved', payload: { aliases: resolved } });
+                break;
+
             case 'synthesize':
                 if (!client || !enginePtr) throw new Error("Engine is not initialized in worker.");
                 self.postMessage({ type: 'synthesis_started' });
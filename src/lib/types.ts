@@ -3,6 +3,12 @@ export type PlaybackStartMode = 'from_start' | 'resume' | 'from_marker';
 
 export interface ProjectSettings {
 	analysisCoreCount: number;
+	// take F0 from the bank's _wav.frq files instead of estimating it
+	useFrq: boolean;
+	// glide from the previous note when a note has no pitchbend of its own
+	autoPortamento: boolean;
+	portamentoLength: number;
+	portamentoStart: number;
 }
 
 export interface PianoRollSettings {
@@ -15,9 +21,79 @@ export interface SettingsState {
 	project: ProjectSettings;
 	pianoRoll: PianoRollSettings;
 }
+export type F0Estimator = 'harvest' | 'dio';
+export interface AnalysisConfig {
+	estimator?: F0Estimator;
+	f0_floor?: number;
+	f0_ceil?: number;
+	frame_period?: number;
+	fft_size?: number;
+	mgc_dims?: number;
+	d4c_threshold?: number;
+	use_frq?: boolean;
+}
+export enum FeatureCacheStatus {
+	Ok = 0,
+	BadMagic = 1,
+	VersionMismatch = 2,
+	ConfigMismatch = 3,
+	SourceMismatch = 4,
+	Corrupt = 5
+}
+export enum FeatureStorage {
+	Full = 0,
+	F32 = 1,
+	Q16 = 2
+}
+export enum SampleStyle {
+	Cv = 0,
+	Vcv = 1
+}
+export interface OtoEntry {
+	filename: string;
+	alias: string;
+	offset: number;
+	consonant: number;
+	cutoff: number;
+	preutterance: number;
+	overlap: number;
+}
+export type OtoIssueKind =
+	| 'malformed'
+	| 'missing_params'
+	| 'invalid_number'
+	| 'consonant_before_preutterance'
+	| 'overlap_after_preutterance'
+	| 'cutoff_before_consonant'
+	| 'cutoff_past_end'
+	| 'missing_wav'
+	| 'invalid_encoding';
+export interface OtoIssue {
+	line: number;
+	filename: string;
+	alias: string;
+	kind: OtoIssueKind;
+	severity: 'error' | 'warning';
+	message: string;
+}
+// curve from a point to the next; UST PBM letters ('s', 'r', 'j', '' for S-curve) work too
+export type PitchShape = 'linear' | 'sine_in' | 'sine_out' | 's_curve' | 's' | 'r' | 'j' | '';
 export interface PitchbendPointInfo {
 	offset: number;
 	value: number;
+	shape?: PitchShape;
+}
+// UTAU VBR fields: length % of the note, period ms, depth cents, fades % of the
+// vibrato, phase % of a cycle, offset % of depth, drift % of frequency
+export interface Vibrato {
+	length: number;
+	period: number;
+	depth: number;
+	fade_in: number;
+	fade_out: number;
+	phase: number;
+	offset: number;
+	drift: number;
 }
 export interface NoteInfo {
 	alias: string;
@@ -29,15 +105,48 @@ export interface NoteInfo {
     velocity: number;
     volume: number;
     modulation: number;
+    voice_color?: string;
+    // an object, or a UST VBR string such as "65,180,35,20,20,0,0,0"
+    vibrato?: Vibrato | string;
 }
+export interface KanaEntry {
+	hiragana: string;
+	katakana: string;
+	romaji: string;
+}
+export type PhonemizerPreset = 'japanese';
 export interface ProjectInfo {
     notes: NoteInfo[];
     tempo: number;
+    phonemizer?: PhonemizerPreset;
+    // null turns the engine's automatic portamento off
+    portamento?: Portamento | null;
+    // cents on top of every note's own pitchbend, spans note boundaries
+    pitch_curve?: CurvePoint[];
+    expressions?: Partial<Record<ExpressionKind, CurvePoint[]>>;
+}
+// dynamics in dB, gender in cents (positive is deeper), the rest -100..100
+export type ExpressionKind = 'dynamics' | 'breathiness' | 'gender' | 'tension' | 'brightness';
+// a point of a project-wide curve, ms from the start of the project
+export interface CurvePoint {
+	time: number;
+	value: number;
+}
+// the same in the editor's beat timeline
+export interface BeatCurvePoint {
+	beat: number;
+	value: number;
+}
+// length in ms, start in ms from the note start (negative begins in the previous note)
+export interface Portamento {
+	length: number;
+	start: number;
 }
 export interface PitchbendPoint {
 	id: string; 
 	offset: number; 
 	value: number; 
+	shape?: PitchShape;
 }
 export interface Note {
 	id: string;
@@ -50,12 +159,57 @@ export interface Note {
 	flags?: string;
 	volume?: number;
 	modulation?: number;
+	voiceColor?: string;
+	vibrato?: Vibrato | string;
+}
+export interface Subbank {
+	color: string;
+	prefix: string;
+	suffix: string;
+	tone_ranges: string[];
+}
+// metadata parsed by the engine from character.txt/yaml, readme.txt and install.txt
+export interface VoicebankMetadata {
+	name: string | null;
+	image: string | null;
+	sample: string | null;
+	author: string | null;
+	voice: string | null;
+	web: string | null;
+	version: string | null;
+	portrait: string | null;
+	portrait_opacity: number | null;
+	default_phonemizer: string | null;
+	singer_type: string | null;
+	subbanks: Subbank[];
+	readme: string | null;
+	install: { folder: string | null; contents_dir: string | null; description: string | null } | null;
+	extra: [string, string][];
+	text_encoding: string;
+}
+// what wsynth_engine_load_zip found and loaded
+export interface ArchiveSummary {
+	root: string;
+	name_encoding: string;
+	oto_files: number;
+	oto_entries: number;
+	samples: number;
+	// samples read from straycat-rs .sc feature files
+	imported: number;
+	analyzed: number;
+}
+export interface VoicebankMetadataFiles {
+	characterTxt?: Uint8Array;
+	characterYaml?: Uint8Array;
+	readmeTxt?: Uint8Array;
+	installTxt?: Uint8Array;
 }
 export interface VoicebankInfo {
 	id: string;
 	name: string;
 	image?: string | null;
 	characterInfo?: Map<string, string>;
+	metadata?: VoicebankMetadata;
 }
 export interface PlaybackState {
 	isPlaying: boolean;
@@ -77,6 +231,9 @@ export interface AppState {
 	playbackMarkerTime: number;
 	synthesis: SynthesisState;
 	tempo: number;
+	// project pitch deviation in cents
+	pitchCurve: BeatCurvePoint[];
+	expressions: Partial<Record<ExpressionKind, BeatCurvePoint[]>>;
 	gridDivision: number;
 	editorMode: EditorMode;
 	isNoteEditorOpen: boolean;
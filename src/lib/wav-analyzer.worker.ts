@@ -1,8 +1,11 @@
 import WSynthModule from './w-synth';
 import type { IWasmApi } from './wsynth-client.svelte';
+import type { AnalysisConfig } from './types';
 
 let wasmApi: {
     analyzeWav: (dataPtr: number, dataLen: number) => number; // returns WasmBuffer ptr
+    analyzeWavWithConfig: (dataPtr: number, dataLen: number, configJson: string) => number;
+    analyzeWavWithFrq: (dataPtr: number, dataLen: number, frqPtr: number, frqLen: number, configJson: string) => number;
     freeBuffer: (bufferPtr: number) => void;
     _malloc: (size: number) => number;
     _free: (ptr: number) => void;
@@ -15,6 +18,8 @@ async function initWasm() {
     const Module = await WSynthModule();
     wasmApi = {
         analyzeWav: Module.cwrap('wsynth_analyze_wav', 'number', ['number', 'number']),
+        analyzeWavWithConfig: Module.cwrap('wsynth_analyze_wav_with_config', 'number', ['number', 'number', 'string']),
+        analyzeWavWithFrq: Module.cwrap('wsynth_analyze_wav_with_frq', 'number', ['number', 'number', 'number', 'number', 'string']),
         freeBuffer: Module.cwrap('wsynth_free_buffer', null, ['number']),
         _malloc: Module._malloc,
         _free: Module._free,
@@ -23,15 +28,25 @@ async function initWasm() {
     };
     console.log('[Worker] WASM Initialized.');
 }
-self.onmessage = async (event: MessageEvent<{ filename: string; wavData: Uint8Array }>) => {
-    const { filename, wavData } = event.data;
+self.onmessage = async (event: MessageEvent<{ filename: string; wavData: Uint8Array; frqData?: Uint8Array; config?: AnalysisConfig }>) => {
+    const { filename, wavData, frqData, config } = event.data;
 
     try {
         await initWasm();
         if (!wasmApi) throw new Error("WASM API not available in worker.");
         const dataPtr = wasmApi._malloc(wavData.length);
         wasmApi.HEAPU8.set(wavData, dataPtr);
-        const resultBufferPtr = wasmApi.analyzeWav(dataPtr, wavData.length);
+        let resultBufferPtr: number;
+        if (frqData && config?.use_frq) {
+            const frqPtr = wasmApi._malloc(frqData.length);
+            wasmApi.HEAPU8.set(frqData, frqPtr);
+            resultBufferPtr = wasmApi.analyzeWavWithFrq(dataPtr, wavData.length, frqPtr, frqData.length, JSON.stringify(config));
+            wasmApi._free(frqPtr);
+        } else {
+            resultBufferPtr = config
+                ? wasmApi.analyzeWavWithConfig(dataPtr, wavData.length, JSON.stringify(config))
+                : wasmApi.analyzeWav(dataPtr, wavData.length);
+        }
         wasmApi._free(dataPtr);
         
         if (resultBufferPtr === 0) {
@@ -1,16 +1,45 @@
 import WSynthModule from './w-synth';
-import type { ProjectInfo } from './types';
+import type {
+    AnalysisConfig,
+    ArchiveSummary,
+    KanaEntry,
+    NoteInfo,
+    OtoEntry,
+    OtoIssue,
+    ProjectInfo,
+    VoicebankMetadata,
+    VoicebankMetadataFiles
+} from './types';
+import { FeatureCacheStatus, FeatureStorage, SampleStyle } from './types';
 
 export interface IWasmApi {
     init: () => void;
     createEngine: () => number;
     destroyEngine: (enginePtr: number) => void;
     loadOto: (enginePtr: number, dataPtr: number, dataLen: number) => boolean;
+    addOto: (enginePtr: number, dir: string, dataPtr: number, dataLen: number) => boolean;
+    validateOto: (enginePtr: number, dir: string, dataPtr: number, dataLen: number) => number;
+    updateOto: (enginePtr: number, entryJson: string) => boolean;
+    writeOto: (enginePtr: number, dir: string) => number;
+    autoOto: (enginePtr: number, style: number) => number;
+    clearVoicebank: (enginePtr: number) => void;
     loadPrefixMap: (enginePtr: number, dataPtr: number, dataLen: number) => boolean;
+    loadVoicebankInfo: (enginePtr: number, ...filePtrsAndLens: number[]) => boolean;
+    voicebankInfo: (enginePtr: number) => number;
+    loadZip: (enginePtr: number, dataPtr: number, dataLen: number, configJson: string | null, threads: number) => number;
+    addVoiceColor: (enginePtr: number, name: string, dir: string | null, dataPtr: number, dataLen: number) => boolean;
     synthesizeProject: (enginePtr: number, jsonStrPtr: number) => number;
+    kanaTable: () => number;
+    resolveAlias: (enginePtr: number, noteJson: string) => number;
     freeBuffer: (bufferPtr: number) => void;
     analyzeWav: (dataPtr: number, dataLen: number) => number;
     cacheFeatures: (enginePtr: number, filename: string, dataPtr: number, dataLen: number) => boolean;
+    setFeatureStorage: (enginePtr: number, storage: number) => boolean;
+    loadPack: (enginePtr: number, dataPtr: number, dataLen: number) => boolean;
+    queueWav: (enginePtr: number, filename: string, dataPtr: number, dataLen: number) => boolean;
+    analyzeQueued: (enginePtr: number, configJson: string | null, threads: number) => number;
+    savePack: (enginePtr: number) => number;
+    checkFeatureCache: (dataPtr: number, dataLen: number, wavPtr: number, wavLen: number, configJson: string | null) => number;
     _malloc: (size: number) => number;
     _free: (ptr: number) => void;
     allocateUTF8: (str: string) => number;
@@ -33,11 +62,29 @@ export class WSynthEngineClient {
             createEngine: Module.cwrap('wsynth_engine_create', 'number', []),
             destroyEngine: Module.cwrap('wsynth_engine_destroy', null, ['number']),
             loadOto: Module.cwrap('wsynth_engine_load_oto', 'boolean', ['number', 'number', 'number']),
+            addOto: Module.cwrap('wsynth_engine_add_oto', 'boolean', ['number', 'string', 'number', 'number']),
+            validateOto: Module.cwrap('wsynth_engine_validate_oto', 'number', ['number', 'string', 'number', 'number']),
+            updateOto: Module.cwrap('wsynth_engine_update_oto', 'boolean', ['number', 'string']),
+            writeOto: Module.cwrap('wsynth_engine_write_oto', 'number', ['number', 'string']),
+            autoOto: Module.cwrap('wsynth_engine_auto_oto', 'number', ['number', 'number']),
+            clearVoicebank: Module.cwrap('wsynth_engine_clear_voicebank', null, ['number']),
             loadPrefixMap: Module.cwrap('wsynth_engine_load_prefix_map', 'boolean', ['number', 'number', 'number']),
+            loadVoicebankInfo: Module.cwrap('wsynth_engine_load_voicebank_info', 'boolean', Array(9).fill('number')),
+            voicebankInfo: Module.cwrap('wsynth_engine_voicebank_info', 'number', ['number']),
+            loadZip: Module.cwrap('wsynth_engine_load_zip', 'number', ['number', 'number', 'number', 'string', 'number']),
+            addVoiceColor: Module.cwrap('wsynth_engine_add_voice_color', 'boolean', ['number', 'string', 'string', 'number', 'number']),
             synthesizeProject: Module.cwrap('wsynth_engine_synthesize_project', 'number', ['number', 'number']),
+            kanaTable: Module.cwrap('wsynth_kana_table', 'number', []),
+            resolveAlias: Module.cwrap('wsynth_engine_resolve_alias', 'number', ['number', 'string']),
             freeBuffer: Module.cwrap('wsynth_free_buffer', null, ['number']),
             analyzeWav: Module.cwrap('wsynth_analyze_wav', 'number', ['number', 'number']),
             cacheFeatures: Module.cwrap('wsynth_engine_cache_features', 'boolean', ['number', 'string', 'number', 'number']),
+            setFeatureStorage: Module.cwrap('wsynth_engine_set_feature_storage', 'boolean', ['number', 'number']),
+            queueWav: Module.cwrap('wsynth_engine_queue_wav', 'boolean', ['number', 'string', 'number', 'number']),
+            analyzeQueued: Module.cwrap('wsynth_engine_analyze_queued', 'number', ['number', 'string', 'number']),
+            loadPack: Module.cwrap('wsynth_engine_load_pack', 'boolean', ['number', 'number', 'number']),
+            savePack: Module.cwrap('wsynth_engine_save_pack', 'number', ['number']),
+            checkFeatureCache: Module.cwrap('wsynth_check_feature_cache', 'number', ['number', 'number', 'number', 'number', 'string']),
             _malloc: Module._malloc,
             _free: Module._free,
             allocateUTF8: Module.allocateUTF8,
@@ -79,10 +126,131 @@ export class WSynthEngineClient {
         return this._loadData(this._api!.loadOto, enginePtr, data);
     }
 
+    async addOto(enginePtr: number, dir: string, data: Uint8Array): Promise<boolean> {
+        return this._loadData(
+            (ptr, dataPtr, dataLen) => this._api!.addOto(ptr, dir, dataPtr, dataLen),
+            enginePtr,
+            data
+        );
+    }
+
+    async validateOto(enginePtr: number, dir: string, data: Uint8Array): Promise<OtoIssue[] | null> {
+        if (!this._api) return null;
+        let bufferPtr = 0;
+        let wasmBufferPtr = 0;
+        try {
+            bufferPtr = this._api._malloc(data.length);
+            if (bufferPtr === 0) return null;
+            this._api.HEAPU8.set(data, bufferPtr);
+            wasmBufferPtr = this._api.validateOto(enginePtr, dir, bufferPtr, data.length);
+            if (wasmBufferPtr === 0) return null;
+
+            const dataPtr = this._api.getValue(wasmBufferPtr, 'i32');
+            const dataLen = this._api.getValue(wasmBufferPtr + 4, 'i32');
+            const json = new TextDecoder().decode(this._api.HEAPU8.subarray(dataPtr, dataPtr + dataLen));
+            return JSON.parse(json) as OtoIssue[];
+        } finally {
+            if (bufferPtr !== 0) this._api._free(bufferPtr);
+            if (wasmBufferPtr !== 0) this._api.freeBuffer(wasmBufferPtr);
+        }
+    }
+
+    updateOto(enginePtr: number, entry: OtoEntry): boolean {
+        return this._api?.updateOto(enginePtr, JSON.stringify(entry)) ?? false;
+    }
+
+    writeOto(enginePtr: number, dir: string): Uint8Array | null {
+        if (!this._api) return null;
+        const wasmBufferPtr = this._api.writeOto(enginePtr, dir);
+        if (wasmBufferPtr === 0) return null;
+        try {
+            const dataPtr = this._api.getValue(wasmBufferPtr, 'i32');
+            const dataLen = this._api.getValue(wasmBufferPtr + 4, 'i32');
+            return this._api.HEAPU8.slice(dataPtr, dataPtr + dataLen);
+        } finally {
+            this._api.freeBuffer(wasmBufferPtr);
+        }
+    }
+
+    autoOto(enginePtr: number, style: SampleStyle): OtoEntry[] | null {
+        if (!this._api) return null;
+        const wasmBufferPtr = this._api.autoOto(enginePtr, style);
+        if (wasmBufferPtr === 0) return null;
+        try {
+            const dataPtr = this._api.getValue(wasmBufferPtr, 'i32');
+            const dataLen = this._api.getValue(wasmBufferPtr + 4, 'i32');
+            const json = new TextDecoder().decode(this._api.HEAPU8.subarray(dataPtr, dataPtr + dataLen));
+            return JSON.parse(json) as OtoEntry[];
+        } finally {
+            this._api.freeBuffer(wasmBufferPtr);
+        }
+    }
+
+    clearVoicebank(enginePtr: number): void {
+        this._api?.clearVoicebank(enginePtr);
+    }
+
     async loadPrefixMap(enginePtr: number, data: Uint8Array): Promise<boolean> {
         return this._loadData(this._api!.loadPrefixMap, enginePtr, data);
     }
 
+    loadVoicebankInfo(enginePtr: number, files: VoicebankMetadataFiles): VoicebankMetadata | null {
+        if (!this._api) return null;
+        const api = this._api;
+        const ordered = [files.characterTxt, files.characterYaml, files.readmeTxt, files.installTxt];
+        const ptrs: number[] = [];
+        try {
+            const args: number[] = [];
+            for (const data of ordered) {
+                if (!data || data.length === 0) {
+                    args.push(0, 0);
+                    continue;
+                }
+                const ptr = api._malloc(data.length);
+                if (ptr === 0) return null;
+                ptrs.push(ptr);
+                api.HEAPU8.set(data, ptr);
+                args.push(ptr, data.length);
+            }
+            if (!api.loadVoicebankInfo(enginePtr, ...args)) return null;
+        } finally {
+            ptrs.forEach((ptr) => api._free(ptr));
+        }
+        return this.voicebankInfo(enginePtr);
+    }
+
+    voicebankInfo(enginePtr: number): VoicebankMetadata | null {
+        if (!this._api) return null;
+        const json = this._readText(this._api.voicebankInfo(enginePtr));
+        return json ? (JSON.parse(json) as VoicebankMetadata) : null;
+    }
+
+    loadZip(enginePtr: number, data: Uint8Array, config: AnalysisConfig | null, threads = 0): ArchiveSummary | null {
+        if (!this._api) return null;
+        let bufferPtr = 0;
+        let wasmBufferPtr = 0;
+        try {
+            bufferPtr = this._api._malloc(data.length);
+            if (bufferPtr === 0) return null;
+            this._api.HEAPU8.set(data, bufferPtr);
+            wasmBufferPtr = this._api.loadZip(enginePtr, bufferPtr, data.length, config ? JSON.stringify(config) : null, threads);
+        } finally {
+            if (bufferPtr !== 0) this._api._free(bufferPtr);
+        }
+        const json = this._readText(wasmBufferPtr);
+        return json ? (JSON.parse(json) as ArchiveSummary) : null;
+    }
+
+    async addVoiceColor(enginePtr: number, name: string, dir: string | null, prefixMap: Uint8Array | null): Promise<boolean> {
+        if (!this._api) return false;
+        if (!prefixMap) return this._api.addVoiceColor(enginePtr, name, dir, 0, 0);
+        return this._loadData(
+            (ptr, dataPtr, dataLen) => this._api!.addVoiceColor(ptr, name, dir, dataPtr, dataLen),
+            enginePtr,
+            prefixMap
+        );
+    }
+
     async cacheFeatures(enginePtr: number, filename: string, featuresData: Uint8Array): Promise<boolean> {
         if (!this._api) return false;
         let bufferPtr = 0;
@@ -96,6 +264,98 @@ export class WSynthEngineClient {
         }
     }
 
+    async queueWav(enginePtr: number, filename: string, wavData: Uint8Array): Promise<boolean> {
+        if (!this._api) return false;
+        let bufferPtr = 0;
+        try {
+            bufferPtr = this._api._malloc(wavData.length);
+            if (bufferPtr === 0) return false;
+            this._api.HEAPU8.set(wavData, bufferPtr);
+            return this._api.queueWav(enginePtr, filename, bufferPtr, wavData.length);
+        } finally {
+            if (bufferPtr !== 0) this._api._free(bufferPtr);
+        }
+    }
+
+    analyzeQueued(enginePtr: number, config: AnalysisConfig | null, threads = 0): number {
+        if (!this._api) return -1;
+        return this._api.analyzeQueued(enginePtr, config ? JSON.stringify(config) : null, threads);
+    }
+
+    async loadPack(enginePtr: number, data: Uint8Array): Promise<boolean> {
+        return this._loadData(this._api!.loadPack, enginePtr, data);
+    }
+
+    async savePack(enginePtr: number): Promise<Uint8Array | null> {
+        if (!this._api) return null;
+        const wasmBufferPtr = this._api.savePack(enginePtr);
+        if (wasmBufferPtr === 0) return null;
+        try {
+            const dataPtr = this._api.getValue(wasmBufferPtr, 'i32');
+            const dataLen = this._api.getValue(wasmBufferPtr + 4, 'i32');
+            if (dataPtr === 0 || dataLen === 0) return null;
+            return this._api.HEAPU8.slice(dataPtr, dataPtr + dataLen);
+        } finally {
+            this._api.freeBuffer(wasmBufferPtr);
+        }
+    }
+
+    setFeatureStorage(enginePtr: number, storage: FeatureStorage): boolean {
+        return this._api?.setFeatureStorage(enginePtr, storage) ?? false;
+    }
+
+    async checkFeatureCache(
+        featuresData: Uint8Array,
+        wavData: Uint8Array | null,
+        config: AnalysisConfig | null
+    ): Promise<FeatureCacheStatus> {
+        if (!this._api) return FeatureCacheStatus.Corrupt;
+        let featuresPtr = 0;
+        let wavPtr = 0;
+        try {
+            featuresPtr = this._api._malloc(featuresData.length);
+            if (featuresPtr === 0) return FeatureCacheStatus.Corrupt;
+            this._api.HEAPU8.set(featuresData, featuresPtr);
+            if (wavData) {
+                wavPtr = this._api._malloc(wavData.length);
+                if (wavPtr === 0) return FeatureCacheStatus.Corrupt;
+                this._api.HEAPU8.set(wavData, wavPtr);
+            }
+            return this._api.checkFeatureCache(
+                featuresPtr,
+                featuresData.length,
+                wavPtr,
+                wavData ? wavData.length : 0,
+                config ? JSON.stringify(config) : null
+            );
+        } finally {
+            if (featuresPtr !== 0) this._api._free(featuresPtr);
+            if (wavPtr !== 0) this._api._free(wavPtr);
+        }
+    }
+
+    private _readText(wasmBufferPtr: number): string | null {
+        if (!this._api || wasmBufferPtr === 0) return null;
+        try {
+            const dataPtr = this._api.getValue(wasmBufferPtr, 'i32');
+            const dataLen = this._api.getValue(wasmBufferPtr + 4, 'i32');
+            return new TextDecoder().decode(this._api.HEAPU8.subarray(dataPtr, dataPtr + dataLen));
+        } finally {
+            this._api.freeBuffer(wasmBufferPtr);
+        }
+    }
+
+    kanaTable(): KanaEntry[] {
+        if (!this._api) return [];
+        const json = this._readText(this._api.kanaTable());
+        return json ? (JSON.parse(json) as KanaEntry[]) : [];
+    }
+
+    resolveAlias(enginePtr: number, note: NoteInfo): string | null {
+        if (!this._api) return null;
+        return this._readText(this._api.resolveAlias(enginePtr, JSON.stringify(note)));
+    }
+
     async synthesizeProject(enginePtr: number, project: ProjectInfo): Promise<Uint8Array | null> {
         if (!this._api) return null;
         const jsonStr = JSON.stringify(project);